@@ -0,0 +1,184 @@
+// Key bindings: which keys trigger which actions. Keys are scancodes, since
+// they're tied to physical key location rather than layout; eg WASD stays in
+// the same place on AZERTY keyboards. Scancodes vary by platform, so bindings
+// can be loaded from a config file, with lines like:
+//
+//     # Comments start with #.
+//     W = MoveForward
+//     17 = MoveForward  # The same key, by scancode.
+//
+// Key names refer to the scancodes of a US PC keyboard.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use types::ParseError;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    // Continuous actions; these happen every frame while their key is held.
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    RotateLeft,
+    RotateRight,
+    RotateUp,
+    RotateDown,
+    RollLeft,
+    RollRight,
+    ZoomIn,
+    ZoomOut,
+
+    // One-shot actions; these happen once when their key is pressed.
+    ToggleStyle,
+    BallAndStick,
+    SpaceFilling,
+    Licorice,
+    Wireframe,
+    FitAll,
+    CenterView,
+}
+
+const ACTION_NAMES: [(&str, Action); 21] = [
+    ("MoveForward", Action::MoveForward),
+    ("MoveBack", Action::MoveBack),
+    ("MoveLeft", Action::MoveLeft),
+    ("MoveRight", Action::MoveRight),
+    ("MoveUp", Action::MoveUp),
+    ("MoveDown", Action::MoveDown),
+    ("RotateLeft", Action::RotateLeft),
+    ("RotateRight", Action::RotateRight),
+    ("RotateUp", Action::RotateUp),
+    ("RotateDown", Action::RotateDown),
+    ("RollLeft", Action::RollLeft),
+    ("RollRight", Action::RollRight),
+    ("ZoomIn", Action::ZoomIn),
+    ("ZoomOut", Action::ZoomOut),
+    ("ToggleStyle", Action::ToggleStyle),
+    ("BallAndStick", Action::BallAndStick),
+    ("SpaceFilling", Action::SpaceFilling),
+    ("Licorice", Action::Licorice),
+    ("Wireframe", Action::Wireframe),
+    ("FitAll", Action::FitAll),
+    ("CenterView", Action::CenterView),
+];
+
+// PC set 1 scancodes, as reported on Windows and Linux.
+const KEY_NAMES: [(&str, u32); 55] = [
+    ("Esc", 1), ("1", 2), ("2", 3), ("3", 4), ("4", 5), ("5", 6), ("6", 7), ("7", 8),
+    ("8", 9), ("9", 10), ("0", 11), ("Minus", 12), ("Equals", 13), ("Backspace", 14),
+    ("Tab", 15), ("Q", 16), ("W", 17), ("E", 18), ("R", 19), ("T", 20), ("Y", 21),
+    ("U", 22), ("I", 23), ("O", 24), ("P", 25), ("Enter", 28), ("LCtrl", 29),
+    ("A", 30), ("S", 31), ("D", 32), ("F", 33), ("G", 34), ("H", 35), ("J", 36),
+    ("K", 37), ("L", 38), ("LShift", 42), ("Z", 44), ("X", 45), ("C", 46), ("V", 47),
+    ("B", 48), ("N", 49), ("M", 50), ("Comma", 51), ("Period", 52), ("RShift", 54),
+    ("LAlt", 56), ("Space", 57), ("Home", 71), ("Up", 72), ("PageUp", 73), ("Left", 75),
+    ("Right", 77), ("Down", 80),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Self> {
+        ACTION_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, a)| *a)
+    }
+}
+
+pub fn key_code(name: &str) -> Option<u32> {
+    // A key's scancode, from its name or its number. Names come first, so "1"
+    // is the 1 key, not scancode 1.
+    KEY_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, c)| *c)
+        .or_else(|| name.parse().ok())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    pub keys: HashMap<u32, Action>,  // <scancode, action>
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keys = [
+            ("W", Action::MoveForward), ("S", Action::MoveBack),
+            ("A", Action::MoveLeft), ("D", Action::MoveRight),
+            ("Space", Action::MoveUp), ("C", Action::MoveDown), ("LCtrl", Action::MoveDown),
+            ("Left", Action::RotateLeft), ("Right", Action::RotateRight),
+            ("Up", Action::RotateUp), ("Down", Action::RotateDown),
+            ("Q", Action::RollLeft), ("E", Action::RollRight),
+            ("Equals", Action::ZoomIn), ("Minus", Action::ZoomOut),
+            ("T", Action::ToggleStyle),
+            ("1", Action::BallAndStick), ("2", Action::SpaceFilling),
+            ("3", Action::Licorice), ("4", Action::Wireframe),
+            ("F", Action::FitAll), ("R", Action::CenterView),
+        ].iter().map(|(k, a)| (key_code(k).unwrap(), *a)).collect();
+
+        Self { keys }
+    }
+}
+
+impl Bindings {
+    pub fn action(&self, code: u32) -> Option<Action> {
+        self.keys.get(&code).cloned()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        // Bindings in a config file replace the defaults for their keys; other
+        // keys keep their default actions.
+        let mut result = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap().trim();
+            let action = parts.next()
+                .ok_or_else(|| ParseError::syntax(i + 1, "Expected KEY = ACTION"))?.trim();
+
+            let code = key_code(key)
+                .ok_or_else(|| ParseError::syntax(i + 1, &format!("Unknown key: {}", key)))?;
+            let action = Action::from_name(action)
+                .ok_or_else(|| ParseError::syntax(i + 1, &format!("Unknown action: {}", action)))?;
+
+            result.keys.insert(code, action);
+        }
+        Ok(result)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ParseError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let bindings = Bindings::parse("\
+# Arrow keys move; IJKL look around.
+Up = MoveForward
+80 = moveback  # Down
+i = RotateUp
+").unwrap();
+        assert_eq!(bindings.action(72), Some(Action::MoveForward));
+        assert_eq!(bindings.action(80), Some(Action::MoveBack));
+        assert_eq!(bindings.action(23), Some(Action::RotateUp));
+        // Defaults are kept for other keys.
+        assert_eq!(bindings.action(17), Some(Action::MoveForward));
+        assert_eq!(bindings.action(100), None);
+        assert_eq!(key_code("4"), Some(5));
+
+        match Bindings::parse("W = Fly") {
+            Err(ParseError::Syntax { line: 1, .. }) => (),
+            other => panic!("{:?}", other),
+        }
+        assert!(Bindings::parse("\n\nBogus = MoveBack").is_err());
+        assert!(Bindings::parse("W MoveBack").is_err());
+    }
+}
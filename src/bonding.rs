@@ -0,0 +1,127 @@
+// Infers bonds from interatomic distances, for files that don't list them,
+// like XYZ, and most of PDB. Two atoms are bonded if they're closer than the sum
+// of their covalent radii, plus a tolerance.
+//
+// We bin atoms into a grid of cubic cells at least as wide as the longest
+// possible bond, so each atom is only compared against atoms in its own and
+// adjacent cells; this is roughly linear in the atom count, rather than O(n²).
+
+use std::collections::HashMap;
+
+use elements;
+use molecule::{Atom, Bond};
+use types::BondOrder;
+
+// Å. Default tolerance, as used by Open Babel and others.
+pub const DEFAULT_TOLERANCE: f32 = 0.45;
+// Å. Atoms closer than this are assumed to be overlapping duplicates, or alternate
+// positions, rather than bonded.
+const MIN_DIST: f32 = 0.4;
+
+pub fn cell(position: [f32; 3], cell_size: f32) -> (i32, i32, i32) {
+    (
+        (position[0] / cell_size).floor() as i32,
+        (position[1] / cell_size).floor() as i32,
+        (position[2] / cell_size).floor() as i32,
+    )
+}
+
+pub fn perceive_bonds(atoms: &[Atom], tolerance: f32) -> Vec<Bond> {
+    // Returns single bonds, each listed once with the lower atom index first,
+    // sorted.
+    let radii: Vec<f32> = atoms.iter()
+        .map(|a| elements::lookup(&a.element).covalent_radius).collect();
+    let max_radius = radii.iter().cloned().fold(0., f32::max);
+    let cell_size = (2. * max_radius + tolerance).max(MIN_DIST);
+
+    let mut grid: HashMap<(i32, i32, i32), Vec<usize>> = HashMap::new();
+    for (i, atom) in atoms.iter().enumerate() {
+        grid.entry(cell(atom.position, cell_size)).or_insert_with(Vec::new).push(i);
+    }
+
+    let mut bonds = Vec::new();
+    for (i, atom) in atoms.iter().enumerate() {
+        let (cx, cy, cz) = cell(atom.position, cell_size);
+
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbors = match grid.get(&(cx + dx, cy + dy, cz + dz)) {
+                        Some(n) => n,
+                        None => continue,
+                    };
+                    for &j in neighbors {
+                        if j <= i {
+                            continue;
+                        }
+                        let p = atoms[j].position;
+                        let d_sq = (p[0] - atom.position[0]).powi(2) +
+                            (p[1] - atom.position[1]).powi(2) + (p[2] - atom.position[2]).powi(2);
+                        let max_dist = radii[i] + radii[j] + tolerance;
+
+                        if d_sq > MIN_DIST.powi(2) && d_sq < max_dist.powi(2) {
+                            bonds.push(Bond::new(i, j, BondOrder::Single));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    bonds.sort_by_key(|b| b.atoms);
+    bonds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn water_and_distant_atom() {
+        let atoms = vec![
+            Atom::new("O", [0., 0., 0.117]),
+            Atom::new("H", [0., 0.757, -0.467]),
+            Atom::new("H", [0., -0.757, -0.467]),
+            Atom::new("Na", [6., 0., 0.]),
+        ];
+        let bonds: Vec<(usize, usize)> = perceive_bonds(&atoms, DEFAULT_TOLERANCE).iter()
+            .map(|b| b.atoms).collect();
+        assert_eq!(bonds, vec![(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        // A lattice with some jitter, straddling cell boundaries and negative
+        // coordinates.
+        let mut atoms = Vec::new();
+        for i in 0..6 {
+            for j in 0..6 {
+                for k in 0..6 {
+                    let jitter = ((i * 7 + j * 13 + k * 29) % 11) as f32 * 0.03;
+                    let element = if (i + j + k) % 3 == 0 { "H" } else { "C" };
+                    atoms.push(Atom::new(element, [
+                        i as f32 * 1.3 - 3. + jitter, j as f32 * 1.3 - 4., k as f32 * 1.3 - jitter
+                    ]));
+                }
+            }
+        }
+
+        let mut expected = Vec::new();
+        for i in 0..atoms.len() {
+            for j in i + 1..atoms.len() {
+                let (a, b) = (atoms[i].position, atoms[j].position);
+                let d = ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt();
+                let max = elements::lookup(&atoms[i].element).covalent_radius +
+                    elements::lookup(&atoms[j].element).covalent_radius + DEFAULT_TOLERANCE;
+                if d > MIN_DIST && d < max {
+                    expected.push((i, j));
+                }
+            }
+        }
+
+        let found: Vec<(usize, usize)> = perceive_bonds(&atoms, DEFAULT_TOLERANCE).iter()
+            .map(|b| b.atoms).collect();
+        assert!(!expected.is_empty());
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,199 @@
+// Periodic table data: masses, radii, and display colors for all 118 elements.
+//
+// Masses are IUPAC standard atomic weights, abridged; for elements without stable
+// isotopes, the mass number of the longest-lived isotope.
+// Covalent radii are from Cordero et al, 2008 (through Cm), and Pyykkö and Atsumi,
+// 2009, beyond. Van der Waals radii are from Bondi, 1964 and Alvarez, 2013, with
+// 2.0 Å used where no value is established. Colors are the Jmol CPK scheme; Jmol
+// has none past Mt, so those use its deep pink for unknown elements.
+
+use xyz::normalize_symbol;
+
+#[derive(Debug)]
+pub struct Element {
+    pub number: u8,  // Protons
+    pub symbol: &'static str,
+    pub name: &'static str,
+    pub mass: f32,  // Da
+    pub covalent_radius: f32,  // Å
+    pub vdw_radius: f32,  // Å
+    pub color: u32,  // 0xRRGGBB
+}
+
+impl Element {
+    pub fn color(&self) -> [f32; 4] {
+        // Color in the RGBA format shapes use.
+        [
+            ((self.color >> 16) & 0xff) as f32 / 255.,
+            ((self.color >> 8) & 0xff) as f32 / 255.,
+            (self.color & 0xff) as f32 / 255.,
+            1.,
+        ]
+    }
+}
+
+// Used for atoms whose element we don't recognize, eg dummy atoms or typos.
+pub static UNKNOWN: Element = Element {
+    number: 0, symbol: "X", name: "Unknown", mass: 0., covalent_radius: 1., vdw_radius: 2.,
+    color: 0xFF1493
+};
+
+pub fn by_number(protons: u8) -> Option<&'static Element> {
+    if protons == 0 {
+        return None;
+    }
+    ELEMENTS.get(protons as usize - 1)
+}
+
+pub fn by_symbol(symbol: &str) -> Option<&'static Element> {
+    // Not case-sensitive; "CL" finds chlorine.
+    let symbol = normalize_symbol(symbol.trim());
+    ELEMENTS.iter().find(|e| e.symbol == symbol)
+}
+
+pub fn lookup(symbol: &str) -> &'static Element {
+    // Like by_symbol, but falls back to UNKNOWN, for drawing.
+    by_symbol(symbol).unwrap_or(&UNKNOWN)
+}
+
+pub static ELEMENTS: [Element; 118] = [
+    Element { number: 1, symbol: "H", name: "Hydrogen", mass: 1.008, covalent_radius: 0.31, vdw_radius: 1.10, color: 0xFFFFFF },
+    Element { number: 2, symbol: "He", name: "Helium", mass: 4.0026, covalent_radius: 0.28, vdw_radius: 1.40, color: 0xD9FFFF },
+    Element { number: 3, symbol: "Li", name: "Lithium", mass: 6.94, covalent_radius: 1.28, vdw_radius: 1.81, color: 0xCC80FF },
+    Element { number: 4, symbol: "Be", name: "Beryllium", mass: 9.0122, covalent_radius: 0.96, vdw_radius: 1.53, color: 0xC2FF00 },
+    Element { number: 5, symbol: "B", name: "Boron", mass: 10.81, covalent_radius: 0.84, vdw_radius: 1.92, color: 0xFFB5B5 },
+    Element { number: 6, symbol: "C", name: "Carbon", mass: 12.011, covalent_radius: 0.76, vdw_radius: 1.70, color: 0x909090 },
+    Element { number: 7, symbol: "N", name: "Nitrogen", mass: 14.007, covalent_radius: 0.71, vdw_radius: 1.55, color: 0x3050F8 },
+    Element { number: 8, symbol: "O", name: "Oxygen", mass: 15.999, covalent_radius: 0.66, vdw_radius: 1.52, color: 0xFF0D0D },
+    Element { number: 9, symbol: "F", name: "Fluorine", mass: 18.998, covalent_radius: 0.57, vdw_radius: 1.47, color: 0x90E050 },
+    Element { number: 10, symbol: "Ne", name: "Neon", mass: 20.180, covalent_radius: 0.58, vdw_radius: 1.54, color: 0xB3E3F5 },
+    Element { number: 11, symbol: "Na", name: "Sodium", mass: 22.990, covalent_radius: 1.66, vdw_radius: 2.27, color: 0xAB5CF2 },
+    Element { number: 12, symbol: "Mg", name: "Magnesium", mass: 24.305, covalent_radius: 1.41, vdw_radius: 1.73, color: 0x8AFF00 },
+    Element { number: 13, symbol: "Al", name: "Aluminium", mass: 26.982, covalent_radius: 1.21, vdw_radius: 1.84, color: 0xBFA6A6 },
+    Element { number: 14, symbol: "Si", name: "Silicon", mass: 28.085, covalent_radius: 1.11, vdw_radius: 2.10, color: 0xF0C8A0 },
+    Element { number: 15, symbol: "P", name: "Phosphorus", mass: 30.974, covalent_radius: 1.07, vdw_radius: 1.80, color: 0xFF8000 },
+    Element { number: 16, symbol: "S", name: "Sulfur", mass: 32.06, covalent_radius: 1.05, vdw_radius: 1.80, color: 0xFFFF30 },
+    Element { number: 17, symbol: "Cl", name: "Chlorine", mass: 35.45, covalent_radius: 1.02, vdw_radius: 1.75, color: 0x1FF01F },
+    Element { number: 18, symbol: "Ar", name: "Argon", mass: 39.948, covalent_radius: 1.06, vdw_radius: 1.88, color: 0x80D1E3 },
+    Element { number: 19, symbol: "K", name: "Potassium", mass: 39.098, covalent_radius: 2.03, vdw_radius: 2.75, color: 0x8F40D4 },
+    Element { number: 20, symbol: "Ca", name: "Calcium", mass: 40.078, covalent_radius: 1.76, vdw_radius: 2.31, color: 0x3DFF00 },
+    Element { number: 21, symbol: "Sc", name: "Scandium", mass: 44.956, covalent_radius: 1.70, vdw_radius: 2.30, color: 0xE6E6E6 },
+    Element { number: 22, symbol: "Ti", name: "Titanium", mass: 47.867, covalent_radius: 1.60, vdw_radius: 2.15, color: 0xBFC2C7 },
+    Element { number: 23, symbol: "V", name: "Vanadium", mass: 50.942, covalent_radius: 1.53, vdw_radius: 2.05, color: 0xA6A6AB },
+    Element { number: 24, symbol: "Cr", name: "Chromium", mass: 51.996, covalent_radius: 1.39, vdw_radius: 2.05, color: 0x8A99C7 },
+    Element { number: 25, symbol: "Mn", name: "Manganese", mass: 54.938, covalent_radius: 1.39, vdw_radius: 2.05, color: 0x9C7AC7 },
+    Element { number: 26, symbol: "Fe", name: "Iron", mass: 55.845, covalent_radius: 1.32, vdw_radius: 2.05, color: 0xE06633 },
+    Element { number: 27, symbol: "Co", name: "Cobalt", mass: 58.933, covalent_radius: 1.26, vdw_radius: 2.00, color: 0xF090A0 },
+    Element { number: 28, symbol: "Ni", name: "Nickel", mass: 58.693, covalent_radius: 1.24, vdw_radius: 2.00, color: 0x50D050 },
+    Element { number: 29, symbol: "Cu", name: "Copper", mass: 63.546, covalent_radius: 1.32, vdw_radius: 2.00, color: 0xC88033 },
+    Element { number: 30, symbol: "Zn", name: "Zinc", mass: 65.38, covalent_radius: 1.22, vdw_radius: 2.10, color: 0x7D80B0 },
+    Element { number: 31, symbol: "Ga", name: "Gallium", mass: 69.723, covalent_radius: 1.22, vdw_radius: 1.87, color: 0xC28F8F },
+    Element { number: 32, symbol: "Ge", name: "Germanium", mass: 72.630, covalent_radius: 1.20, vdw_radius: 2.11, color: 0x668F8F },
+    Element { number: 33, symbol: "As", name: "Arsenic", mass: 74.922, covalent_radius: 1.19, vdw_radius: 1.85, color: 0xBD80E3 },
+    Element { number: 34, symbol: "Se", name: "Selenium", mass: 78.971, covalent_radius: 1.20, vdw_radius: 1.90, color: 0xFFA100 },
+    Element { number: 35, symbol: "Br", name: "Bromine", mass: 79.904, covalent_radius: 1.20, vdw_radius: 1.83, color: 0xA62929 },
+    Element { number: 36, symbol: "Kr", name: "Krypton", mass: 83.798, covalent_radius: 1.16, vdw_radius: 2.02, color: 0x5CB8D1 },
+    Element { number: 37, symbol: "Rb", name: "Rubidium", mass: 85.468, covalent_radius: 2.20, vdw_radius: 3.03, color: 0x702EB0 },
+    Element { number: 38, symbol: "Sr", name: "Strontium", mass: 87.62, covalent_radius: 1.95, vdw_radius: 2.49, color: 0x00FF00 },
+    Element { number: 39, symbol: "Y", name: "Yttrium", mass: 88.906, covalent_radius: 1.90, vdw_radius: 2.40, color: 0x94FFFF },
+    Element { number: 40, symbol: "Zr", name: "Zirconium", mass: 91.224, covalent_radius: 1.75, vdw_radius: 2.30, color: 0x94E0E0 },
+    Element { number: 41, symbol: "Nb", name: "Niobium", mass: 92.906, covalent_radius: 1.64, vdw_radius: 2.15, color: 0x73C2C9 },
+    Element { number: 42, symbol: "Mo", name: "Molybdenum", mass: 95.95, covalent_radius: 1.54, vdw_radius: 2.10, color: 0x54B5B5 },
+    Element { number: 43, symbol: "Tc", name: "Technetium", mass: 98., covalent_radius: 1.47, vdw_radius: 2.05, color: 0x3B9E9E },
+    Element { number: 44, symbol: "Ru", name: "Ruthenium", mass: 101.07, covalent_radius: 1.46, vdw_radius: 2.05, color: 0x248F8F },
+    Element { number: 45, symbol: "Rh", name: "Rhodium", mass: 102.91, covalent_radius: 1.42, vdw_radius: 2.00, color: 0x0A7D8C },
+    Element { number: 46, symbol: "Pd", name: "Palladium", mass: 106.42, covalent_radius: 1.39, vdw_radius: 2.05, color: 0x006985 },
+    Element { number: 47, symbol: "Ag", name: "Silver", mass: 107.87, covalent_radius: 1.45, vdw_radius: 2.10, color: 0xC0C0C0 },
+    Element { number: 48, symbol: "Cd", name: "Cadmium", mass: 112.41, covalent_radius: 1.44, vdw_radius: 2.20, color: 0xFFD98F },
+    Element { number: 49, symbol: "In", name: "Indium", mass: 114.82, covalent_radius: 1.42, vdw_radius: 2.20, color: 0xA67573 },
+    Element { number: 50, symbol: "Sn", name: "Tin", mass: 118.71, covalent_radius: 1.39, vdw_radius: 1.93, color: 0x668080 },
+    Element { number: 51, symbol: "Sb", name: "Antimony", mass: 121.76, covalent_radius: 1.39, vdw_radius: 2.17, color: 0x9E63B5 },
+    Element { number: 52, symbol: "Te", name: "Tellurium", mass: 127.60, covalent_radius: 1.38, vdw_radius: 2.06, color: 0xD47A00 },
+    Element { number: 53, symbol: "I", name: "Iodine", mass: 126.90, covalent_radius: 1.39, vdw_radius: 1.98, color: 0x940094 },
+    Element { number: 54, symbol: "Xe", name: "Xenon", mass: 131.29, covalent_radius: 1.40, vdw_radius: 2.16, color: 0x429EB0 },
+    Element { number: 55, symbol: "Cs", name: "Caesium", mass: 132.91, covalent_radius: 2.44, vdw_radius: 3.43, color: 0x57178F },
+    Element { number: 56, symbol: "Ba", name: "Barium", mass: 137.33, covalent_radius: 2.15, vdw_radius: 2.68, color: 0x00C900 },
+    Element { number: 57, symbol: "La", name: "Lanthanum", mass: 138.91, covalent_radius: 2.07, vdw_radius: 2.50, color: 0x70D4FF },
+    Element { number: 58, symbol: "Ce", name: "Cerium", mass: 140.12, covalent_radius: 2.04, vdw_radius: 2.48, color: 0xFFFFC7 },
+    Element { number: 59, symbol: "Pr", name: "Praseodymium", mass: 140.91, covalent_radius: 2.03, vdw_radius: 2.47, color: 0xD9FFC7 },
+    Element { number: 60, symbol: "Nd", name: "Neodymium", mass: 144.24, covalent_radius: 2.01, vdw_radius: 2.45, color: 0xC7FFC7 },
+    Element { number: 61, symbol: "Pm", name: "Promethium", mass: 145., covalent_radius: 1.99, vdw_radius: 2.43, color: 0xA3FFC7 },
+    Element { number: 62, symbol: "Sm", name: "Samarium", mass: 150.36, covalent_radius: 1.98, vdw_radius: 2.42, color: 0x8FFFC7 },
+    Element { number: 63, symbol: "Eu", name: "Europium", mass: 151.96, covalent_radius: 1.98, vdw_radius: 2.40, color: 0x61FFC7 },
+    Element { number: 64, symbol: "Gd", name: "Gadolinium", mass: 157.25, covalent_radius: 1.96, vdw_radius: 2.38, color: 0x45FFC7 },
+    Element { number: 65, symbol: "Tb", name: "Terbium", mass: 158.93, covalent_radius: 1.94, vdw_radius: 2.37, color: 0x30FFC7 },
+    Element { number: 66, symbol: "Dy", name: "Dysprosium", mass: 162.50, covalent_radius: 1.92, vdw_radius: 2.35, color: 0x1FFFC7 },
+    Element { number: 67, symbol: "Ho", name: "Holmium", mass: 164.93, covalent_radius: 1.92, vdw_radius: 2.33, color: 0x00FF9C },
+    Element { number: 68, symbol: "Er", name: "Erbium", mass: 167.26, covalent_radius: 1.89, vdw_radius: 2.32, color: 0x00E675 },
+    Element { number: 69, symbol: "Tm", name: "Thulium", mass: 168.93, covalent_radius: 1.90, vdw_radius: 2.30, color: 0x00D452 },
+    Element { number: 70, symbol: "Yb", name: "Ytterbium", mass: 173.05, covalent_radius: 1.87, vdw_radius: 2.28, color: 0x00BF38 },
+    Element { number: 71, symbol: "Lu", name: "Lutetium", mass: 174.97, covalent_radius: 1.87, vdw_radius: 2.27, color: 0x00AB24 },
+    Element { number: 72, symbol: "Hf", name: "Hafnium", mass: 178.49, covalent_radius: 1.75, vdw_radius: 2.25, color: 0x4DC2FF },
+    Element { number: 73, symbol: "Ta", name: "Tantalum", mass: 180.95, covalent_radius: 1.70, vdw_radius: 2.20, color: 0x4DA6FF },
+    Element { number: 74, symbol: "W", name: "Tungsten", mass: 183.84, covalent_radius: 1.62, vdw_radius: 2.10, color: 0x2194D6 },
+    Element { number: 75, symbol: "Re", name: "Rhenium", mass: 186.21, covalent_radius: 1.51, vdw_radius: 2.05, color: 0x267DAB },
+    Element { number: 76, symbol: "Os", name: "Osmium", mass: 190.23, covalent_radius: 1.44, vdw_radius: 2.00, color: 0x266696 },
+    Element { number: 77, symbol: "Ir", name: "Iridium", mass: 192.22, covalent_radius: 1.41, vdw_radius: 2.00, color: 0x175487 },
+    Element { number: 78, symbol: "Pt", name: "Platinum", mass: 195.08, covalent_radius: 1.36, vdw_radius: 2.05, color: 0xD0D0E0 },
+    Element { number: 79, symbol: "Au", name: "Gold", mass: 196.97, covalent_radius: 1.36, vdw_radius: 2.10, color: 0xFFD123 },
+    Element { number: 80, symbol: "Hg", name: "Mercury", mass: 200.59, covalent_radius: 1.32, vdw_radius: 2.05, color: 0xB8B8D0 },
+    Element { number: 81, symbol: "Tl", name: "Thallium", mass: 204.38, covalent_radius: 1.45, vdw_radius: 1.96, color: 0xA6544D },
+    Element { number: 82, symbol: "Pb", name: "Lead", mass: 207.2, covalent_radius: 1.46, vdw_radius: 2.02, color: 0x575961 },
+    Element { number: 83, symbol: "Bi", name: "Bismuth", mass: 208.98, covalent_radius: 1.48, vdw_radius: 2.07, color: 0x9E4FB5 },
+    Element { number: 84, symbol: "Po", name: "Polonium", mass: 209., covalent_radius: 1.40, vdw_radius: 1.97, color: 0xAB5C00 },
+    Element { number: 85, symbol: "At", name: "Astatine", mass: 210., covalent_radius: 1.50, vdw_radius: 2.02, color: 0x754F45 },
+    Element { number: 86, symbol: "Rn", name: "Radon", mass: 222., covalent_radius: 1.50, vdw_radius: 2.20, color: 0x428296 },
+    Element { number: 87, symbol: "Fr", name: "Francium", mass: 223., covalent_radius: 2.60, vdw_radius: 3.48, color: 0x420066 },
+    Element { number: 88, symbol: "Ra", name: "Radium", mass: 226., covalent_radius: 2.21, vdw_radius: 2.83, color: 0x007D00 },
+    Element { number: 89, symbol: "Ac", name: "Actinium", mass: 227., covalent_radius: 2.15, vdw_radius: 2.00, color: 0x70ABFA },
+    Element { number: 90, symbol: "Th", name: "Thorium", mass: 232.04, covalent_radius: 2.06, vdw_radius: 2.40, color: 0x00BAFF },
+    Element { number: 91, symbol: "Pa", name: "Protactinium", mass: 231.04, covalent_radius: 2.00, vdw_radius: 2.00, color: 0x00A1FF },
+    Element { number: 92, symbol: "U", name: "Uranium", mass: 238.03, covalent_radius: 1.96, vdw_radius: 2.30, color: 0x008FFF },
+    Element { number: 93, symbol: "Np", name: "Neptunium", mass: 237., covalent_radius: 1.90, vdw_radius: 2.00, color: 0x0080FF },
+    Element { number: 94, symbol: "Pu", name: "Plutonium", mass: 244., covalent_radius: 1.87, vdw_radius: 2.00, color: 0x006BFF },
+    Element { number: 95, symbol: "Am", name: "Americium", mass: 243., covalent_radius: 1.80, vdw_radius: 2.00, color: 0x545CF2 },
+    Element { number: 96, symbol: "Cm", name: "Curium", mass: 247., covalent_radius: 1.69, vdw_radius: 2.00, color: 0x785CE3 },
+    Element { number: 97, symbol: "Bk", name: "Berkelium", mass: 247., covalent_radius: 1.68, vdw_radius: 2.00, color: 0x8A4FE3 },
+    Element { number: 98, symbol: "Cf", name: "Californium", mass: 251., covalent_radius: 1.68, vdw_radius: 2.00, color: 0xA136D4 },
+    Element { number: 99, symbol: "Es", name: "Einsteinium", mass: 252., covalent_radius: 1.65, vdw_radius: 2.00, color: 0xB31FD4 },
+    Element { number: 100, symbol: "Fm", name: "Fermium", mass: 257., covalent_radius: 1.67, vdw_radius: 2.00, color: 0xB31FBA },
+    Element { number: 101, symbol: "Md", name: "Mendelevium", mass: 258., covalent_radius: 1.73, vdw_radius: 2.00, color: 0xB30DA6 },
+    Element { number: 102, symbol: "No", name: "Nobelium", mass: 259., covalent_radius: 1.76, vdw_radius: 2.00, color: 0xBD0D87 },
+    Element { number: 103, symbol: "Lr", name: "Lawrencium", mass: 266., covalent_radius: 1.61, vdw_radius: 2.00, color: 0xC70066 },
+    Element { number: 104, symbol: "Rf", name: "Rutherfordium", mass: 267., covalent_radius: 1.57, vdw_radius: 2.00, color: 0xCC0059 },
+    Element { number: 105, symbol: "Db", name: "Dubnium", mass: 268., covalent_radius: 1.49, vdw_radius: 2.00, color: 0xD1004F },
+    Element { number: 106, symbol: "Sg", name: "Seaborgium", mass: 269., covalent_radius: 1.43, vdw_radius: 2.00, color: 0xD90045 },
+    Element { number: 107, symbol: "Bh", name: "Bohrium", mass: 270., covalent_radius: 1.41, vdw_radius: 2.00, color: 0xE00038 },
+    Element { number: 108, symbol: "Hs", name: "Hassium", mass: 269., covalent_radius: 1.34, vdw_radius: 2.00, color: 0xE6002E },
+    Element { number: 109, symbol: "Mt", name: "Meitnerium", mass: 278., covalent_radius: 1.29, vdw_radius: 2.00, color: 0xEB0026 },
+    Element { number: 110, symbol: "Ds", name: "Darmstadtium", mass: 281., covalent_radius: 1.28, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 111, symbol: "Rg", name: "Roentgenium", mass: 282., covalent_radius: 1.21, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 112, symbol: "Cn", name: "Copernicium", mass: 285., covalent_radius: 1.22, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 113, symbol: "Nh", name: "Nihonium", mass: 286., covalent_radius: 1.36, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 114, symbol: "Fl", name: "Flerovium", mass: 289., covalent_radius: 1.43, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 115, symbol: "Mc", name: "Moscovium", mass: 290., covalent_radius: 1.62, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 116, symbol: "Lv", name: "Livermorium", mass: 293., covalent_radius: 1.75, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 117, symbol: "Ts", name: "Tennessine", mass: 294., covalent_radius: 1.65, vdw_radius: 2.00, color: 0xFF1493 },
+    Element { number: 118, symbol: "Og", name: "Oganesson", mass: 294., covalent_radius: 1.57, vdw_radius: 2.00, color: 0xFF1493 },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups() {
+        for (i, element) in ELEMENTS.iter().enumerate() {
+            assert_eq!(element.number as usize, i + 1);
+            assert_eq!(by_symbol(element.symbol).unwrap().number, element.number);
+        }
+
+        let cl = by_symbol("CL").unwrap();
+        assert_eq!(cl.name, "Chlorine");
+        assert_eq!(by_number(17).unwrap().symbol, "Cl");
+        assert_eq!(by_number(8).unwrap().color(), [1., 13. / 255., 13. / 255., 1.]);
+        assert!(by_number(0).is_none());
+        assert!(by_number(119).is_none());
+        assert_eq!(lookup("Qq").symbol, "X");
+    }
+}
@@ -4,11 +4,15 @@ layout(location = 0) in vec3 v_normal;
 layout(location = 1) in vec4 face_color2;
 layout(location = 2) in vec3 diffuse_direction;
 layout(location = 3) in vec4 ambient_color;
+layout(location = 4) in float highlight;
 // todo diffuse_color unused
 
 
 layout(location = 0) out vec4 f_color;
 
+// Selected shapes are tinted towards this.
+const vec3 highlight_color = vec3(1., 0.85, 0.2);
+
 //    float diffuse_weight = max(dot(norm, dir), 0.);
 //    return uniforms.diffuse_color * diffuse_weight * uniforms.diffuse_intensity;
 
@@ -17,5 +21,6 @@ void main() {
     vec3 dark_color = vec3(ambient_color);
     vec3 regular_color = vec3(face_color2);
 
-    f_color = vec4(mix(dark_color, regular_color, brightness), 1.0);
+    vec3 shaded = mix(dark_color, regular_color, brightness);
+    f_color = vec4(mix(shaded, highlight_color, highlight * 0.5), 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,107 @@
+// Renders scenes to images without a window, eg for figures generated in batch
+// jobs. We use Vulkan when a device is available, and fall back to the CPU
+// rasterizer otherwise.
+
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use png;
+
+use raster::{self, SoftwareRenderer};
+use render::VulkanRenderer;
+use renderer::Renderer;
+use types::{Camera, Scene};
+
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,  // RGBA, 8 bits per channel, starting with the top row.
+}
+
+pub fn render(scene: &Scene, cam: &Camera, width: u32, height: u32,
+              background: [f32; 4]) -> Image {
+    // The camera's aspect ratio is set from the image dimensions.
+    let mut cam = cam.clone();
+    cam.aspect = width as f32 / height as f32;
+
+    let renderer: Box<Renderer> = match VulkanRenderer::offscreen([width, height]) {
+        Ok(r) => Box::new(r),
+        Err(e) => {
+            eprintln!("Can't render with Vulkan ({}); using the software renderer.", e);
+            Box::new(SoftwareRenderer::new([width, height]))
+        }
+    };
+
+    let scene = Scene { cam, ..scene.clone() };
+    let pixels = draw(renderer, &scene, background);
+
+    Image { width, height, pixels }
+}
+
+fn draw(mut renderer: Box<Renderer>, scene: &Scene, background: [f32; 4]) -> Vec<u8> {
+    // Draw a frame at the renderer's size, with the software renderer if it fails.
+    renderer.set_background(background);
+    renderer.upload_scene(scene);
+
+    // Vulkan can still fail on the first frame, eg if the device is lost.
+    renderer.read_pixels().unwrap_or_else(|e| {
+        eprintln!("Can't render with Vulkan ({}); using the software renderer.", e);
+        let dimensions = renderer.dimensions();
+        raster::render(scene, &scene.cam, dimensions[0], dimensions[1], background)
+    })
+}
+
+pub fn save_png(image: &Image, path: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(file, image.width, image.height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+
+    encoder.write_header()
+        .and_then(|mut writer| writer.write_image_data(&image.pixels))
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use molecule::{Atom, Molecule};
+    use scenes;
+    use types::Representation;
+
+    struct LostDevice;
+
+    impl Renderer for LostDevice {
+        // Fails the way Vulkan does when its device is lost mid-frame.
+        fn upload_scene(&mut self, _scene: &Scene) {}
+        fn update_uniforms(&mut self, _scene: &Scene) {}
+        fn set_background(&mut self, _color: [f32; 4]) {}
+        fn draw_frame(&mut self) -> Result<(), String> {
+            Err("device lost".to_string())
+        }
+        fn resize(&mut self, _dimensions: [u32; 2]) {}
+        fn dimensions(&self) -> [u32; 2] {
+            [16, 8]
+        }
+        fn read_pixels(&mut self) -> Result<Vec<u8>, String> {
+            self.draw_frame().map(|_| Vec::new())
+        }
+    }
+
+    #[test]
+    fn fallback() {
+        let molecule = Molecule::new("O", vec![Atom::new("O", [0., 0., 0.])], Vec::new());
+        let mut scene = scenes::from_molecule(1., &molecule, Representation::SpaceFilling);
+        scene.cam.aspect = 2.;
+        let background = [0., 0., 0., 1.];
+
+        // A failed frame is drawn by the software renderer instead, the same as
+        // if we'd used it from the start.
+        let expected = draw(Box::new(SoftwareRenderer::new([16, 8])), &scene, background);
+        assert_eq!(draw(Box::new(LostDevice), &scene, background), expected);
+        assert_eq!(expected, raster::render(&scene, &scene.cam, 16, 8, background));
+        assert!(expected.chunks(4).any(|px| px[..3] != [0, 0, 0]));
+    }
+}
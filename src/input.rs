@@ -1,12 +1,89 @@
 // Handles keyboard and mouse input.
 use std::f32::consts::PI;
 
+use bindings::{Action, Bindings};
 use ops::{add_arr, mul_arr};
-use transforms;
-use types::{CameraType, Scene, Vec4};
+use orbit;
+use scenes;
+use types::{Camera, CameraType, Quaternion, Representation, Scene};
 
 const τ: f32 = 2. * PI;
 
+// A press and release closer together than this, in pixels, is a click rather
+// than a drag.
+const CLICK_DISTANCE: f32 = 4.;
+// Radians per pixel dragged, for free and single-shape cameras.
+const DRAG_SENSITIVITY: f32 = 0.005;
+// Fraction of the distance to the target per scroll line, for orbit cameras.
+const SCROLL_DOLLY: f32 = 0.1;
+// Zooming keeps the fov in this range; a zero or half-turn fov makes the
+// projection infinite.
+const MIN_FOV: f32 = 0.01;
+const MAX_FOV: f32 = τ / 2. - 0.01;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MouseEvent {
+    // Windowing-library-independent mouse events. Positions are in pixels, from
+    // the top left of the window.
+    Moved([f32; 2]),
+    Pressed(MouseButton),
+    Released(MouseButton),
+    Scrolled(f32),  // Lines; positive is away from the user.
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Click {
+    pub button: MouseButton,
+    pub position: [f32; 2],  // Pixels, from the top left.
+    pub shift: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    // Keyboard and mouse state, kept between frames.
+    pub keys: Vec<u32>,  // Scancodes of keys held down.
+    pub cursor: Option<[f32; 2]>,  // None until the cursor first enters the window.
+    pub buttons: Vec<MouseButton>,  // Held down.
+    pub shift: bool,
+    // Where each held button was pressed, to tell clicks from drags.
+    press_positions: Vec<(MouseButton, [f32; 2])>,
+    // Clicks since they were last taken; see `take_clicks`.
+    clicks: Vec<Click>,
+    // Shift-dragging with the left button draws a selection box, rather than
+    // rotating. Where the current box started, and finished boxes since they
+    // were last taken; see `take_boxes`.
+    box_start: Option<[f32; 2]>,
+    boxes: Vec<([f32; 2], [f32; 2])>,
+}
+
+impl InputState {
+    pub fn take_clicks(&mut self) -> Vec<Click> {
+        // Clicks are consumed, eg by picking, once per frame.
+        self.clicks.drain(..).collect()
+    }
+
+    pub fn take_boxes(&mut self) -> Vec<([f32; 2], [f32; 2])> {
+        // Selection boxes, as opposite corners in pixels from the top left.
+        self.boxes.drain(..).collect()
+    }
+}
+
+pub fn to_ndc(position: [f32; 2], dimensions: [u32; 2]) -> [f32; 2] {
+    // Convert pixels from the top left to -1 to 1, left to right and bottom to
+    // top. Note that y is up, unlike Vulkan's clip space.
+    [
+        2. * position[0] / dimensions[0] as f32 - 1.,
+        1. - 2. * position[1] / dimensions[1] as f32,
+    ]
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum MoveDirection{
     Forward,
@@ -17,8 +94,31 @@ pub enum MoveDirection{
     Down,
 }
 
-pub fn move_camera(direction: MoveDirection, θ: &[f32; 3], amount: f32) -> [f32; 3] {
-    // Move the camera to a new position, based on where it's pointing.
+fn yaw(orientation: &Quaternion) -> f32 {
+    // Rotation about the world's up axis; 0 faces +z, and positive turns right.
+    // Taken from the right axis, which pitch doesn't tilt, so it's defined
+    // even when looking straight up or down.
+    let right = orientation.rotate_vec(&[1., 0., 0.]);
+    (-right[2]).atan2(right[0])
+}
+
+fn pitch(orientation: &Quaternion) -> f32 {
+    // Angle above the horizon; positive is up.
+    orientation.rotate_vec(&[0., 0., 1.])[1].max(-1.).min(1.).asin()
+}
+
+fn fps_orientation(yaw: f32, pitch: f32) -> Quaternion {
+    // Yaw, then pitch; no roll. Pitch is clamped to ±τ/4, from looking straight
+    // down to straight up.
+    let pitch = pitch.max(-τ / 4.).min(τ / 4.);
+    Quaternion::from_axis_angle(&[0., 1., 0.], yaw) * Quaternion::from_axis_angle(&[1., 0., 0.], -pitch)
+}
+
+pub fn move_camera(direction: MoveDirection, orientation: &Quaternion, horizontal: bool,
+                   amount: f32) -> [f32; 3] {
+    // The offset to move the camera by, relative to where it's pointing. With
+    // `horizontal`, as for FPS cameras, forward, back, left, and right stay in
+    // the horizontal plane, and up and down are world up and down.
     let unit_vec = match direction {
         MoveDirection::Forward => [0., 0., 1.],
         MoveDirection::Back => [0., 0., -1.],
@@ -28,144 +128,375 @@ pub fn move_camera(direction: MoveDirection, θ: &[f32; 3], amount: f32) -> [f32
         MoveDirection::Down => [0., -1., 0.],
     };
 
-//    let v = transforms::dot_mv4(transforms::rotate(&θ), Vec4::from_array(&unit_vec));
-//    let v: Vec4 = unit_vec.into();
+    let orientation = if horizontal {
+        Quaternion::from_axis_angle(&[0., 1., 0.], yaw(orientation))
+    } else {
+        *orientation
+    };
+    mul_arr(&orientation.rotate_vec(&unit_vec), amount)
+}
 
-//    [
-//        v.x * amount, v.y * amount, v.z * amount
-//    ]
+fn rotate_camera(cam: &mut Camera, cam_type: &CameraType, axis: &[f32; 3], angle: f32) {
+    // Rotate about one of the camera's axes, eg [0., 1., 0.] for its up axis.
+    // Free cameras compose quaternions, which avoids gimbal lock. FPS cameras
+    // yaw about the world's up axis instead, can't roll, and can't pitch past
+    // straight up or down.
+    match cam_type {
+        CameraType::FPS => {
+            let (yaw, pitch) = (yaw(&cam.orientation), pitch(&cam.orientation));
+            cam.orientation = fps_orientation(yaw + axis[1] * angle, pitch - axis[0] * angle);
+        }
+        _ => cam.orientation = (cam.orientation * Quaternion::from_axis_angle(axis, angle)).normalize(),
+    }
+}
 
-    mul_arr(&unit_vec, amount)
+fn rotation(action: Action) -> Option<([f32; 3], f32, usize, f32)> {
+    // The camera axis and direction an action rotates about, and the Euler
+    // angle and direction it rotates single shapes by. Camera rotations are about
+    // the camera's own axes: yaw about up, pitch about right, and roll about forward.
+    match action {
+        Action::RotateLeft => Some(([0., 1., 0.], -1., 1, -1.)),
+        Action::RotateRight => Some(([0., 1., 0.], 1., 1, 1.)),
+        Action::RotateDown => Some(([1., 0., 0.], 1., 0, -1.)),
+        Action::RotateUp => Some(([1., 0., 0.], -1., 0, 1.)),
+        Action::RollLeft => Some(([0., 0., 1.], -1., 2, -1.)),
+        Action::RollRight => Some(([0., 0., 1.], 1., 2, 1.)),
+        _ => None,
+    }
+}
 
+fn move_direction(action: Action) -> Option<MoveDirection> {
+    match action {
+        Action::MoveForward => Some(MoveDirection::Forward),
+        Action::MoveBack => Some(MoveDirection::Back),
+        Action::MoveLeft => Some(MoveDirection::Left),
+        Action::MoveRight => Some(MoveDirection::Right),
+        Action::MoveUp => Some(MoveDirection::Up),
+        Action::MoveDown => Some(MoveDirection::Down),
+        _ => None,
+    }
 }
 
-pub fn handle_pressed<'a>(pressed: &[u32], delta_time: f32,
-                      scene: &'a mut Scene) -> () {
-    // shape is only used when displaying single shapes.
-    // delta_time is in seconds.
+pub fn handle_pressed(pressed: &[u32], bindings: &Bindings, delta_time: f32, scene: &mut Scene) {
+    // Continuous actions, for keys held down. delta_time is in seconds.
     let move_amount = scene.sensitivities.0 * delta_time;
     let rotate_amount = scene.sensitivities.1 * delta_time;
     let zoom_amount = scene.sensitivities.2 * delta_time;
+    // Orbit cameras dolly at the move speed, and pan in fractions of the view.
+    let dolly_amount = (move_amount / scene.orbit.distance).min(0.5);
+    let pan_amount = rotate_amount / 2.;
 
-    // Code shorteners
-    let shape = scene.shapes.get_mut(&0).unwrap();
-
-    for code in pressed {
-        match *code {
-            17 => {  // W
-                match scene.cam_type {
-                    CameraType::Single => (),
-                    _ => scene.cam.position = add_arr(&scene.cam.position, &move_camera(MoveDirection::Forward, &scene.cam.θ, move_amount))
-                }
-            },
-            31 => {  // S
-                match scene.cam_type {
-                    CameraType::Single => (),
-                    _ => scene.cam.position = add_arr(&scene.cam.position, &move_camera(MoveDirection::Back, &scene.cam.θ, move_amount))
-                }
-            },
-            30 => {  // A
-                match scene.cam_type {
-                    CameraType::Single => (),
-                    _ => scene.cam.position = add_arr(&scene.cam.position, &move_camera(MoveDirection::Left, &scene.cam.θ, move_amount))
-                }
-            },
-            32 => {  // D
-                match scene.cam_type {
-                    CameraType::Single => (),
-                    _ => scene.cam.position = add_arr(&scene.cam.position, &move_camera(MoveDirection::Right, &scene.cam.θ, move_amount))
-                }
-            },
-            46 => {  // C
-                match scene.cam_type {
-                    CameraType::Single => (),
-                    CameraType::FPS => (),
-                    _ => scene.cam.position = add_arr(&scene.cam.position, &move_camera(MoveDirection::Down, &scene.cam.θ, move_amount))
-                }
-            },
-            29 => {  // Lctrl
-                match scene.cam_type {
-                    CameraType::Single => (),
-                    CameraType::FPS => (),
-                    _ => scene.cam.position = add_arr(&scene.cam.position, &move_camera(MoveDirection::Down, &scene.cam.θ, move_amount))
+    for action in pressed.iter().filter_map(|code| bindings.action(*code)) {
+        if let Some(direction) = move_direction(action) {
+            match (&scene.cam_type, direction) {
+                (CameraType::Single, _) => (),
+                (CameraType::Orbit, MoveDirection::Forward) =>
+                    orbit::dolly(&mut scene.cam, &mut scene.orbit, dolly_amount),
+                (CameraType::Orbit, MoveDirection::Back) =>
+                    orbit::dolly(&mut scene.cam, &mut scene.orbit, -dolly_amount),
+                (CameraType::Orbit, MoveDirection::Left) =>
+                    orbit::pan(&mut scene.cam, &mut scene.orbit, -pan_amount, 0.),
+                (CameraType::Orbit, MoveDirection::Right) =>
+                    orbit::pan(&mut scene.cam, &mut scene.orbit, pan_amount, 0.),
+                (CameraType::Orbit, MoveDirection::Up) =>
+                    orbit::pan(&mut scene.cam, &mut scene.orbit, 0., pan_amount),
+                (CameraType::Orbit, MoveDirection::Down) =>
+                    orbit::pan(&mut scene.cam, &mut scene.orbit, 0., -pan_amount),
+                (CameraType::FPS, MoveDirection::Up) | (CameraType::FPS, MoveDirection::Down) => (),
+                _ => {
+                    let horizontal = scene.cam_type == CameraType::FPS;
+                    let offset = move_camera(direction, &scene.cam.orientation, horizontal, move_amount);
+                    scene.cam.position = add_arr(&scene.cam.position, &offset);
                 }
-            },
-            57 => {  // Space
-                match scene.cam_type {
-                    CameraType::Single => (),
-                    CameraType::FPS => (),
-                    _ => scene.cam.position = add_arr(&scene.cam.position, &move_camera(MoveDirection::Up, &scene.cam.θ, move_amount))
-                }
-            },
- 
-            // Rotations around Y and Z range from 0 to τ. (clockwise rotation).
-            // X rotations range from -τ/4 to τ/4 (Looking straight down to up)
-            75 => {  // Left
-                match scene.cam_type {
-                    CameraType::Single => shape.orientation[1] -= rotate_amount,
-                    _ => scene.cam.θ[1] -= rotate_amount
-                }
-            },
-            77 => {  // Right
-                match scene.cam_type {
-                    CameraType::Single => shape.orientation[1] += rotate_amount,
-                    _ => scene.cam.θ[1] += rotate_amount
-                }
-            },
-            // Don't allow us to look greater than τ/4 up or down.
-            80 => {  // Down
-                match scene.cam_type {
-                    CameraType::Single => shape.orientation[0] -= rotate_amount,
-                    _ => scene.cam.θ[0] -= rotate_amount
-                }
-            },
-            72 => {  // Up
-                match scene.cam_type {
-                    CameraType::Single => shape.orientation[0] += rotate_amount,
-                    _ => scene.cam.θ[0] += rotate_amount
-                }
-            },
-            16 => {  // Q
-                match scene.cam_type {
-                    CameraType::Single => shape.orientation[2] -= rotate_amount,
-                    _ => scene.cam.θ[2] -= rotate_amount
+            }
+        } else if let Some((axis, sign, euler_axis, euler_sign)) = rotation(action) {
+            match scene.cam_type {
+                // Not every scene has a shape 0; eg molecules drawn as licorice.
+                CameraType::Single => if let Some(shape) = scene.shapes.get_mut(&0) {
+                    shape.orientation[euler_axis] += euler_sign * rotate_amount
+                },
+                CameraType::Orbit => orbit::rotate(&mut scene.cam, &scene.orbit, &axis, sign * rotate_amount),
+                _ => rotate_camera(&mut scene.cam, &scene.cam_type, &axis, sign * rotate_amount),
+            }
+        } else {
+            match action {
+                Action::ZoomIn => scene.cam.fov = (scene.cam.fov - zoom_amount).max(MIN_FOV),
+                Action::ZoomOut => scene.cam.fov = (scene.cam.fov + zoom_amount).min(MAX_FOV),
+                _ => (),
+            }
+        }
+    }
+}
+
+fn handle_drag(button: MouseButton, from: [f32; 2], to: [f32; 2], dimensions: [u32; 2],
+               scene: &mut Scene) {
+    // Left drags rotate; with orbit cameras, right and middle drags pan.
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+
+    match (button, &scene.cam_type) {
+        (MouseButton::Left, CameraType::Orbit) => orbit::arcball(
+            &mut scene.cam, &scene.orbit, to_ndc(from, dimensions), to_ndc(to, dimensions)
+        ),
+        (_, CameraType::Orbit) => {
+            // Keep the point under the cursor there; ndc spans 2 half-heights.
+            let scale = 2. / dimensions[1] as f32;
+            orbit::pan(&mut scene.cam, &mut scene.orbit, -dx * scale, dy * scale)
+        }
+        (MouseButton::Left, CameraType::Single) => {
+            if let Some(shape) = scene.shapes.get_mut(&0) {
+                shape.orientation[1] += dx * DRAG_SENSITIVITY;
+                shape.orientation[0] += dy * DRAG_SENSITIVITY;
+            }
+        }
+        (MouseButton::Left, _) => {
+            rotate_camera(&mut scene.cam, &scene.cam_type, &[0., 1., 0.], dx * DRAG_SENSITIVITY);
+            rotate_camera(&mut scene.cam, &scene.cam_type, &[1., 0., 0.], dy * DRAG_SENSITIVITY);
+        }
+        _ => (),
+    }
+}
+
+pub fn handle_mouse(event: MouseEvent, state: &mut InputState, dimensions: [u32; 2],
+                    scene: &mut Scene) {
+    // Update mouse state, and the camera, for one event. Completed clicks are
+    // queued on `state`.
+    match event {
+        MouseEvent::Moved(position) => {
+            if let (Some(prev), None) = (state.cursor, state.box_start) {
+                if let Some(button) = state.buttons.first().cloned() {
+                    handle_drag(button, prev, position, dimensions, scene);
                 }
-            },
-            18 => {  // E
-                match scene.cam_type {
-                    CameraType::Single => shape.orientation[2] += rotate_amount,
-                    _ => scene.cam.θ[2] += rotate_amount
+            }
+            state.cursor = Some(position);
+        }
+        MouseEvent::Pressed(button) => {
+            if !state.buttons.contains(&button) {
+                state.buttons.push(button);
+            }
+            if let Some(position) = state.cursor {
+                state.press_positions.retain(|(b, _)| *b != button);
+                state.press_positions.push((button, position));
+                if button == MouseButton::Left && state.shift {
+                    state.box_start = Some(position);
                 }
-            },
+            }
+        }
+        MouseEvent::Released(button) => {
+            state.buttons.retain(|b| *b != button);
 
-            // Zoom
-            13 => {  // +
-                scene.cam.fov -= zoom_amount; // todo specify const
-                if scene.cam.fov < 0. { scene.cam.fov = 0. }
-            },
-            12 => {  // -
-                scene.cam.fov += zoom_amount;
-                if scene.cam.fov > τ { scene.cam.fov = τ }
+            let pressed_at = state.press_positions.iter().find(|(b, _)| *b == button).map(|(_, p)| *p);
+            state.press_positions.retain(|(b, _)| *b != button);
 
+            let box_start = if button == MouseButton::Left { state.box_start.take() } else { None };
+
+            if let (Some(start), Some(position)) = (pressed_at, state.cursor) {
+                let moved = ((position[0] - start[0]).powi(2) + (position[1] - start[1]).powi(2)).sqrt();
+                if moved < CLICK_DISTANCE {
+                    state.clicks.push(Click { button, position, shift: state.shift });
+                } else if let Some(box_start) = box_start {
+                    state.boxes.push((box_start, position));
+                }
+            }
+        }
+        MouseEvent::Scrolled(lines) => match scene.cam_type {
+            CameraType::Orbit => orbit::dolly(&mut scene.cam, &mut scene.orbit, lines * SCROLL_DOLLY),
+            _ => {
+                scene.cam.fov = (scene.cam.fov - lines * scene.sensitivities.2 * 0.1).max(MIN_FOV).min(MAX_FOV);
             }
-//            2 =>{ scene = &mut scene_lib[&1].clone()},  // 1
-//            2 =>{ scene = &mut scenes::cube_scene(4./3.)},  // 1
-//            3 => scene = &mut scene_lib[&2].clone(),  // 2
-
-            // todo reimplement some of these
-//            Keycode::V => cam.near -= 1. * ZOOM_SENSITIVITY,
-//            Keycode::B => cam.near += 1. * ZOOM_SENSITIVITY,
-//
-//            Keycode::N => cam.far -= 1. * ZOOM_SENSITIVITY,
-//            Keycode::M => cam.far += 1. * ZOOM_SENSITIVITY,
-//
-//            Keycode::Minus => cam.fov += 1. * ZOOM_SENSITIVITY,
-//            Keycode::Equals => cam.fov -= 1. * ZOOM_SENSITIVITY,
-//
-//            // reset
-//            Keycode::Backspace => cam = DEFAULT_CAMERA(),
-            _ => (),
+        },
+    }
+}
+
+pub fn handle_key_down(code: u32, bindings: &Bindings, scene: &mut Scene) {
+    // Actions that happen once when a key is pressed, rather than continuously
+    // while it's held. Changes to the scene's representation rebuild its shapes;
+    // the renderer must re-upload them.
+    let representation = match bindings.action(code) {
+        Some(Action::FitAll) => {
+            // Fit everything in view, and orbit it.
+            scene.cam_type = CameraType::Orbit;
+            orbit::fit_all(scene);
+            return;
+        }
+        Some(Action::CenterView) => {
+            // Orbit the selected atoms, or the center of the scene if there aren't any.
+            scene.cam_type = CameraType::Orbit;
+            let selected: Vec<[f32; 3]> = match scene.molecule {
+                Some(ref molecule) => {
+                    let positions = scenes::atom_positions(molecule);
+                    scene.selection.iter().map(|i| positions[*i]).collect()
+                }
+                None => Vec::new(),
+            };
+            let points = if selected.is_empty() {
+                vec![orbit::bounding_sphere(&scene.shapes).0]
+            } else {
+                selected
+            };
+            orbit::center_on(&mut scene.cam, &mut scene.orbit, &points);
+            return;
         }
+        Some(Action::ToggleStyle) => scene.representation.next(),
+        Some(Action::BallAndStick) => Representation::BallAndStick,
+        Some(Action::SpaceFilling) => Representation::SpaceFilling,
+        Some(Action::Licorice) => Representation::Licorice,
+        Some(Action::Wireframe) => Representation::Wireframe,
+        _ => return,
+    };
+
+    if representation != scene.representation {
+        scenes::set_representation(scene, representation);
     }
+}
 
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse() {
+        let mut scene = scenes::scene_1(1.);
+        scene.cam_type = CameraType::Orbit;
+        let mut state = InputState::default();
+        let dims = [200, 100];
+
+        // A press and release in place is a click.
+        handle_mouse(MouseEvent::Moved([50., 50.]), &mut state, dims, &mut scene);
+        handle_mouse(MouseEvent::Pressed(MouseButton::Left), &mut state, dims, &mut scene);
+        handle_mouse(MouseEvent::Moved([51., 50.]), &mut state, dims, &mut scene);
+        handle_mouse(MouseEvent::Released(MouseButton::Left), &mut state, dims, &mut scene);
+        let clicks = state.take_clicks();
+        assert_eq!(clicks.len(), 1);
+        assert_eq!(clicks[0].position, [51., 50.]);
+        assert!(state.take_clicks().is_empty());
+
+        // A drag rotates the camera around its target, and isn't a click.
+        let position = scene.cam.position;
+        handle_mouse(MouseEvent::Pressed(MouseButton::Left), &mut state, dims, &mut scene);
+        handle_mouse(MouseEvent::Moved([120., 50.]), &mut state, dims, &mut scene);
+        handle_mouse(MouseEvent::Released(MouseButton::Left), &mut state, dims, &mut scene);
+        assert!(state.take_clicks().is_empty());
+        assert!(scene.cam.position[0] < position[0] - 0.1);
+        let distance = scene.orbit.distance;
+        let d = scene.cam.position;
+        assert!(((d[0].powi(2) + d[1].powi(2) + d[2].powi(2)).sqrt() - distance).abs() < 1e-3);
+
+        // Shift-dragging draws a selection box instead of rotating.
+        let position = scene.cam.position;
+        state.shift = true;
+        handle_mouse(MouseEvent::Pressed(MouseButton::Left), &mut state, dims, &mut scene);
+        handle_mouse(MouseEvent::Moved([150., 80.]), &mut state, dims, &mut scene);
+        handle_mouse(MouseEvent::Released(MouseButton::Left), &mut state, dims, &mut scene);
+        state.shift = false;
+        assert_eq!(scene.cam.position, position);
+        assert_eq!(state.take_boxes(), vec![([120., 50.], [150., 80.])]);
+        assert!(state.take_clicks().is_empty());
+
+        // Scrolling towards the screen dollies in.
+        handle_mouse(MouseEvent::Scrolled(1.), &mut state, dims, &mut scene);
+        assert!(scene.orbit.distance < distance);
+
+        assert_eq!(to_ndc([0., 0.], dims), [-1., 1.]);
+        assert_eq!(to_ndc([200., 100.], dims), [1., -1.]);
+    }
+
+    #[test]
+    fn keys() {
+        let bindings = Bindings::default();
+
+        // Holding W and D with a free camera moves forward and right.
+        let mut scene = scenes::scene_1(1.);
+        scene.cam_type = CameraType::Free;
+        let position = scene.cam.position;
+        handle_pressed(&[17, 32], &bindings, 0.5, &mut scene);
+        assert!(scene.cam.position[2] > position[2]);
+        assert!(scene.cam.position[0] > position[0]);
+        assert_eq!(scene.cam.position[1], position[1]);
+
+        // FPS cameras don't fly.
+        scene.cam_type = CameraType::FPS;
+        let position = scene.cam.position;
+        handle_pressed(&[57, 46], &bindings, 0.5, &mut scene);
+        assert_eq!(scene.cam.position, position);
+
+        // Opposite keys cancel out; unbound keys do nothing.
+        let orientation = scene.cam.orientation;
+        handle_pressed(&[75, 77, 100], &bindings, 0.5, &mut scene);
+        assert!((scene.cam.orientation.w - orientation.w).abs() < 1e-5);
+        assert!((scene.cam.orientation.y - orientation.y).abs() < 1e-5);
+
+        // W dollies orbit cameras in, rather than moving them freely.
+        scene.cam_type = CameraType::Orbit;
+        orbit::sync(&mut scene.cam, &scene.orbit);
+        let distance = scene.orbit.distance;
+        handle_pressed(&[17], &bindings, 0.5, &mut scene);
+        assert!(scene.orbit.distance < distance);
+
+        // Rebinding moves actions to other keys.
+        let bindings = Bindings::parse("Up = MoveForward\nI = ZoomIn").unwrap();
+        let distance = scene.orbit.distance;
+        let fov = scene.cam.fov;
+        handle_pressed(&[72, 23], &bindings, 0.5, &mut scene);
+        assert!(scene.orbit.distance < distance);
+        assert!(scene.cam.fov < fov);
+        // Holding zoom stops short of a zero fov.
+        handle_pressed(&[23], &bindings, 100., &mut scene);
+        assert_eq!(scene.cam.fov, MIN_FOV);
+
+        // One-shot actions.
+        handle_key_down(5, &bindings, &mut scene);  // 4
+        assert_eq!(scene.representation, Representation::Wireframe);
+        scene.cam_type = CameraType::Free;
+        handle_key_down(33, &bindings, &mut scene);  // F
+        assert_eq!(scene.cam_type, CameraType::Orbit);
+    }
+
+    #[test]
+    fn view_relative() {
+        let bindings = Bindings::default();
+        let close = |a: [f32; 3], b: [f32; 3]| (0..3).all(|i| (a[i] - b[i]).abs() < 1e-4);
+
+        // After turning right a quarter turn, W moves along +x.
+        let mut scene = scenes::scene_1(1.);
+        scene.cam_type = CameraType::Free;
+        rotate_camera(&mut scene.cam, &CameraType::Free, &[0., 1., 0.], τ / 4.);
+        let position = scene.cam.position;
+        handle_pressed(&[17], &bindings, 1., &mut scene);
+        let moved = scene.sensitivities.0;
+        assert!(close(scene.cam.position, [position[0] + moved, position[1], position[2]]));
+
+        // Looking up, free cameras climb, but FPS cameras stay level.
+        let look_up = |scene: &mut Scene| {
+            let cam_type = scene.cam_type.clone();
+            rotate_camera(&mut scene.cam, &cam_type, &[1., 0., 0.], -τ / 8.)
+        };
+        let mut scene = scenes::scene_1(1.);
+        scene.cam_type = CameraType::Free;
+        look_up(&mut scene);
+        let position = scene.cam.position;
+        handle_pressed(&[17], &bindings, 1., &mut scene);
+        assert!(scene.cam.position[1] > position[1] + 0.1);
+
+        scene.cam_type = CameraType::FPS;
+        let position = scene.cam.position;
+        handle_pressed(&[17], &bindings, 1., &mut scene);
+        assert!((scene.cam.position[1] - position[1]).abs() < 1e-5);
+        assert!((scene.cam.position[2] - position[2] - moved).abs() < 1e-4);
+
+        // FPS cameras can't look past straight up, or roll, and keep their yaw.
+        rotate_camera(&mut scene.cam, &CameraType::FPS, &[0., 1., 0.], 0.5);
+        for _ in 0..10 {
+            look_up(&mut scene);
+        }
+        assert!((pitch(&scene.cam.orientation) - τ / 4.).abs() < 1e-3);
+        assert!((yaw(&scene.cam.orientation) - 0.5).abs() < 1e-4);
+        rotate_camera(&mut scene.cam, &CameraType::FPS, &[0., 0., 1.], 1.);
+        assert!(scene.cam.right()[1].abs() < 1e-5);
+
+        // Left stays horizontal while looking straight up.
+        let position = scene.cam.position;
+        handle_pressed(&[30], &bindings, 1., &mut scene);
+        let expected = [-0.5f32.cos() * moved, 0., 0.5f32.sin() * moved];
+        assert!(close(scene.cam.position, add_arr(&position, &expected)));
+    }
+}
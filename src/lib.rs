@@ -6,6 +6,52 @@
 #![feature(vec_remove_item)]
 #![feature(const_vec_new)]
 
+// Molecule and scene construction, mesh generation, transforms, and rendering,
+// to a window or to images. Build a `Scene` (eg with `scenes::from_molecule`),
+// then show it with `render`, or render it to an image with `headless::render`.
+
+#[macro_use]
+extern crate ndarray;
+
+extern crate num;
+extern crate png;
+
+// Vulkano
+#[macro_use]
+extern crate vulkano;
+#[macro_use]
+extern crate vulkano_shader_derive;
+
+extern crate winit;
+// The `vulkano_win` crate is the link between `vulkano` and `winit`. Vulkano doesn't know about
+// winit, and winit doesn't know about vulkano, so import a crate that will provide a link between
+// the two.
+extern crate vulkano_win;
+
+pub mod bindings;
+pub mod bonding;
+pub mod elements;
+pub mod headless;
+pub mod input;
+pub mod mol;
+pub mod molecule;
+pub mod ops;
+pub mod orbit;
+pub mod pdb;
+pub mod pick;
+pub mod query;
+pub mod raster;
+pub mod scenes;
+pub mod selection;
+pub mod shape_maker;
+pub mod types;
+pub mod transforms;
+pub mod render;
+pub mod renderer;
+pub mod xyz;
+
+pub use render::render;
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -1,37 +1,228 @@
-// We use mathematical conventions that may direct upper-case var names,
-// or lower-case constants.
-#![allow(non_snake_case)]
-#![allow(non_upper_case_globals)]
-#![feature(non_ascii_idents)]
-#![feature(vec_remove_item)]
-#![feature(const_vec_new)]
-
-#[macro_use]
-extern crate ndarray;
-
-extern crate num;
-
-// Vulkano
-#[macro_use]
-extern crate vulkano;
-#[macro_use]
-extern crate vulkano_shader_derive;
-
-extern crate winit;
-// The `vulkano_win` crate is the link between `vulkano` and `winit`. Vulkano doesn't know about
-// winit, and winit doesn't know about vulkano, so import a crate that will provide a link between
-// the two.
-extern crate vulkano_win;
-
-mod input;
-mod ops;
-mod scenes;
-mod shape_maker;
-mod types;
-mod transforms;
-mod render;
+// A viewer for the chemgraphics library.
+
+extern crate chemgraphics;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use chemgraphics::{headless, molecule, query, scenes, selection};
+use chemgraphics::bindings::Bindings;
+use chemgraphics::types::Representation;
+
+const USAGE: &str = "\
+Usage:
+    chemgraphics                        Show a demo scene.
+    chemgraphics view FILE [OPTIONS]    Open FILE in the viewer.
+    chemgraphics render FILE [OPTIONS]  Render FILE to a PNG image.
+
+FILE is XYZ, PDB, or MOL/SDF, detected by its extension.
+
+Options:
+    --style STYLE    ball-stick (default), space-filling, licorice, or wireframe
+    --bg COLOR       Background; a name, eg white, or hex, eg #203040. Default black.
+    -o, --out PATH   Image to write, for render. Defaults to FILE with a .png extension.
+    --size WxH       Image size in pixels, for render. Default 1024x768.
+    --keys PATH      Key bindings for the viewer; lines like \"W = MoveForward\".
+    --select EXPR    Select and highlight atoms, eg \"chain A and within 5 of resname LIG\".";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Demo,
+    Help,
+    View,
+    Render,
+}
+
+#[derive(Debug, PartialEq)]
+struct Args {
+    command: Command,
+    file: Option<PathBuf>,
+    output: Option<PathBuf>,
+    size: [u32; 2],
+    style: Representation,
+    background: [f32; 4],
+    keys: Option<PathBuf>,
+    select: Option<String>,
+}
+
+fn parse_color(text: &str) -> Option<[f32; 4]> {
+    let named = match text.to_lowercase().as_ref() {
+        "black" => Some([0., 0., 0.]),
+        "white" => Some([1., 1., 1.]),
+        "gray" | "grey" => Some([0.5, 0.5, 0.5]),
+        "red" => Some([1., 0., 0.]),
+        "green" => Some([0., 1., 0.]),
+        "blue" => Some([0., 0., 1.]),
+        _ => None,
+    };
+    if let Some(c) = named {
+        return Some([c[0], c[1], c[2], 1.]);
+    }
+
+    // Hex, eg #203040, with an optional leading #.
+    let hex = text.trim_start_matches('#');
+    if !hex.is_ascii() || hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok().map(|v| v as f32 / 255.);
+    Some([channel(0)?, channel(2)?, channel(4)?, 1.])
+}
+
+fn parse_size(text: &str) -> Option<[u32; 2]> {
+    let mut parts = text.split('x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || width == 0 || height == 0 {
+        return None;
+    }
+    Some([width, height])
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    // `args` excludes the program name.
+    let mut result = Args {
+        command: Command::Demo,
+        file: None,
+        output: None,
+        size: [1024, 768],
+        style: Representation::BallAndStick,
+        background: [0., 0., 0., 1.],
+        keys: None,
+        select: None,
+    };
+
+    let mut args = args.iter();
+    result.command = match args.next().map(|a| a.as_ref()) {
+        None => return Ok(result),
+        Some("view") => Command::View,
+        Some("render") => Command::Render,
+        Some("-h") | Some("--help") | Some("help") => Command::Help,
+        Some(other) => return Err(format!("Unknown command: {}", other)),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+
+        match arg.as_ref() {
+            "--style" => {
+                let v = value()?;
+                result.style = Representation::from_name(v).ok_or(format!("Unknown style: {}", v))?;
+            }
+            "--bg" => {
+                let v = value()?;
+                result.background = parse_color(v).ok_or(format!("Invalid color: {}", v))?;
+            }
+            "-o" | "--out" => result.output = Some(PathBuf::from(value()?)),
+            "--size" => {
+                let v = value()?;
+                result.size = parse_size(v).ok_or(format!("Invalid size: {}", v))?;
+            }
+            "--keys" => result.keys = Some(PathBuf::from(value()?)),
+            "--select" => result.select = Some(value()?.to_string()),
+            "-h" | "--help" => result.command = Command::Help,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if result.file.is_none() => result.file = Some(PathBuf::from(arg)),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    if result.command != Command::Help && result.file.is_none() {
+        return Err("Missing FILE".to_string());
+    }
+    Ok(result)
+}
 
 fn main() {
-    render::render();
-    
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+
+    let aspect = args.size[0] as f32 / args.size[1] as f32;
+
+    let mut scene = match args.file {
+        Some(ref file) => {
+            let molecule = molecule::load(file).unwrap_or_else(|e| {
+                eprintln!("Can't load {}: {}", file.display(), e);
+                process::exit(1);
+            });
+            scenes::from_molecule(aspect, &molecule, args.style)
+        }
+        None => scenes::scene_1(aspect),
+    };
+
+    if let Some(ref text) = args.select {
+        let atoms = match scene.molecule {
+            Some(ref molecule) => query::select(molecule, text),
+            None => Ok(Vec::new()),
+        };
+        scene.selection = atoms.unwrap_or_else(|e| {
+            eprintln!("Invalid selection \"{}\": {}", text, e);
+            process::exit(2);
+        });
+        selection::add_measurement_shapes(&mut scene);
+    }
+
+    match args.command {
+        Command::Help => println!("{}", USAGE),
+        // The aspect ratio is synced with the window once it's open.
+        Command::Demo | Command::View => {
+            let bindings = match args.keys {
+                Some(ref path) => Bindings::load(path).unwrap_or_else(|e| {
+                    eprintln!("Can't load key bindings from {}: {}", path.display(), e);
+                    process::exit(1);
+                }),
+                None => Bindings::default(),
+            };
+            chemgraphics::render::render_with_bindings(scene, bindings, args.background)
+        }
+        Command::Render => {
+            let output = args.output.clone()
+                .unwrap_or_else(|| args.file.as_ref().unwrap().with_extension("png"));
+
+            let image = headless::render(&scene, &scene.cam, args.size[0], args.size[1],
+                                         args.background);
+            if let Err(e) = headless::save_png(&image, &output) {
+                eprintln!("Can't write {}: {}", output.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Result<Args, String> {
+        parse_args(&text.split_whitespace().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(args("").unwrap().command, Command::Demo);
+
+        let a = args("view water.xyz --style licorice --bg white --keys keys.txt").unwrap();
+        assert_eq!(a.command, Command::View);
+        assert_eq!(a.file, Some(PathBuf::from("water.xyz")));
+        assert_eq!(a.style, Representation::Licorice);
+        assert_eq!(a.background, [1., 1., 1., 1.]);
+        assert_eq!(a.keys, Some(PathBuf::from("keys.txt")));
+
+        let a = args("render 1abc.pdb -o out.png --size 640x480 --bg #ff0000 --select backbone").unwrap();
+        assert_eq!(a.command, Command::Render);
+        assert_eq!(a.output, Some(PathBuf::from("out.png")));
+        assert_eq!(a.size, [640, 480]);
+        assert_eq!(a.background, [1., 0., 0., 1.]);
+        assert_eq!(a.select, Some("backbone".to_string()));
+
+        assert!(args("view").is_err());
+        assert!(args("view a.xyz --style fuzzy").is_err());
+        assert!(args("view a.xyz --bg").is_err());
+        assert!(args("view a.xyz --bg aééb").is_err());
+        assert!(args("render a.xyz --size 640").is_err());
+        assert!(args("open a.xyz").is_err());
+    }
+}
@@ -0,0 +1,542 @@
+// Reads and writes MDL molfiles, and SDF files, which are molfiles separated
+// by `$$$$` lines, each optionally followed by data fields. We read both the
+// V2000 (fixed-column) and V3000 (tokenized) connection table formats, and
+// write V2000. Spec: CTfile Formats, BIOVIA.
+
+use std::fs;
+use std::path::Path;
+
+use types::{BondOrder, ParseError};
+use xyz::normalize_symbol;
+
+#[derive(Clone, Debug)]
+pub struct MolAtom {
+    pub element: String,
+    pub position: [f32; 3],
+    pub charge: i8,
+    pub mass_number: Option<u16>,  // Set for isotope-labeled atoms only.
+}
+
+#[derive(Clone, Debug)]
+pub struct MolBond {
+    pub atoms: (usize, usize),  // 0-based indices into the atom list.
+    pub order: BondOrder,
+    pub stereo: u8,  // V2000 stereo code, eg 1 for a wedge; 0 for none.
+}
+
+#[derive(Clone, Debug)]
+pub struct MolRecord {
+    pub name: String,
+    pub program: String,  // Second header line; program and timestamp.
+    pub comment: String,
+    pub atoms: Vec<MolAtom>,
+    pub bonds: Vec<MolBond>,
+    pub data: Vec<(String, String)>,  // SDF data fields, as (name, value).
+}
+
+fn col(line: &str, start: usize, end: usize) -> &str {
+    // 0-based, end-exclusive, trimmed; short lines yield empty columns.
+    let end = end.min(line.len());
+    if start >= end {
+        return "";
+    }
+    line.get(start..end).unwrap_or("").trim()
+}
+
+fn bond_order(code: u8) -> BondOrder {
+    // Query bond types (5 - 8) are drawn as single bonds.
+    match code {
+        2 => BondOrder::Double,
+        3 => BondOrder::Triple,
+        4 => BondOrder::Aromatic,
+        _ => BondOrder::Single,
+    }
+}
+
+fn bond_code(order: BondOrder) -> u8 {
+    match order {
+        BondOrder::Single => 1,
+        BondOrder::Double => 2,
+        BondOrder::Triple => 3,
+        BondOrder::Aromatic => 4,
+    }
+}
+
+fn stereo_from_cfg(cfg: u8) -> u8 {
+    // V3000 bond CFG values to V2000 stereo codes: up, either, and down.
+    match cfg {
+        1 => 1,
+        2 => 4,
+        3 => 6,
+        _ => 0,
+    }
+}
+
+fn stereo_cfg(stereo: u8) -> u8 {
+    match stereo {
+        1 => 1,
+        4 => 2,
+        6 => 3,
+        _ => 0,
+    }
+}
+
+fn charge_from_code(code: u8) -> i8 {
+    // V2000 atom-block charge codes. 4 is a doublet radical, not a charge.
+    match code {
+        1 => 3,
+        2 => 2,
+        3 => 1,
+        5 => -1,
+        6 => -2,
+        7 => -3,
+        _ => 0,
+    }
+}
+
+fn charge_code(charge: i8) -> u8 {
+    match charge {
+        1..=3 => 4 - charge as u8,
+        -3..=-1 => (4 - charge) as u8,
+        _ => 0,
+    }
+}
+
+fn parse_num<T: ::std::str::FromStr>(text: &str, line: usize, label: &str) -> Result<T, ParseError> {
+    text.trim().parse().map_err(|_| ParseError::syntax(line, &format!("Invalid {}", label)))
+}
+
+fn parse_v2000(lines: &[(usize, &str)], counts: (usize, &str))
+        -> Result<(Vec<MolAtom>, Vec<MolBond>), ParseError> {
+    // `lines` starts just after the counts line.
+    let num_atoms: usize = parse_num(col(counts.1, 0, 3), counts.0, "atom count")?;
+    let num_bonds: usize = parse_num(col(counts.1, 3, 6), counts.0, "bond count")?;
+
+    if lines.len() < num_atoms + num_bonds {
+        let last = lines.last().map(|l| l.0).unwrap_or(counts.0);
+        return Err(ParseError::syntax(last, "File ended inside the atom or bond block"));
+    }
+
+    let mut atoms = Vec::with_capacity(num_atoms);
+    for &(i, line) in &lines[..num_atoms] {
+        atoms.push(MolAtom {
+            element: normalize_symbol(col(line, 31, 34)),
+            position: [parse_num(col(line, 0, 10), i, "x coordinate")?,
+                       parse_num(col(line, 10, 20), i, "y coordinate")?,
+                       parse_num(col(line, 20, 30), i, "z coordinate")?],
+            charge: charge_from_code(col(line, 36, 39).parse().unwrap_or(0)),
+            // The atom-block mass difference is relative to the element's
+            // default mass; we only read absolute masses, from `M  ISO`.
+            mass_number: None,
+        });
+    }
+
+    let mut bonds = Vec::with_capacity(num_bonds);
+    for &(i, line) in &lines[num_atoms..num_atoms + num_bonds] {
+        let a: usize = parse_num(col(line, 0, 3), i, "bond atom")?;
+        let b: usize = parse_num(col(line, 3, 6), i, "bond atom")?;
+        if a == 0 || b == 0 || a > num_atoms || b > num_atoms {
+            return Err(ParseError::syntax(i, "Bond refers to a nonexistent atom"));
+        }
+        bonds.push(MolBond {
+            atoms: (a - 1, b - 1),
+            order: bond_order(parse_num(col(line, 6, 9), i, "bond type")?),
+            stereo: col(line, 9, 12).parse().unwrap_or(0),
+        });
+    }
+
+    // Property lines. `M  CHG` and `M  ISO` supersede atom-block values.
+    let mut charges_reset = false;
+    for &(i, line) in &lines[num_atoms + num_bonds..] {
+        if line.starts_with("M  END") {
+            break;
+        }
+        let is_chg = line.starts_with("M  CHG");
+        if !(is_chg || line.starts_with("M  ISO")) {
+            continue;
+        }
+        if is_chg && !charges_reset {
+            for atom in &mut atoms { atom.charge = 0 }
+            charges_reset = true;
+        }
+
+        let vals: Vec<&str> = line[6..].split_whitespace().skip(1).collect();
+        for pair in vals.chunks(2) {
+            if pair.len() < 2 { break }
+            let atom: usize = parse_num(pair[0], i, "property atom")?;
+            if atom == 0 || atom > atoms.len() {
+                return Err(ParseError::syntax(i, "Property refers to a nonexistent atom"));
+            }
+            if is_chg {
+                atoms[atom - 1].charge = parse_num(pair[1], i, "charge")?;
+            } else {
+                atoms[atom - 1].mass_number = Some(parse_num(pair[1], i, "isotope")?);
+            }
+        }
+    }
+
+    Ok((atoms, bonds))
+}
+
+fn v3000_lines<'a>(lines: &[(usize, &'a str)]) -> Vec<(usize, String)> {
+    // Strip the `M  V30 ` prefix, and join lines continued with a trailing `-`.
+    let mut result: Vec<(usize, String)> = Vec::new();
+    let mut continuing = false;
+
+    for &(i, line) in lines {
+        if line.starts_with("M  END") {
+            break;
+        }
+        if !line.starts_with("M  V30 ") {
+            continue;
+        }
+        let mut content = line[7..].trim_end().to_string();
+        let continues = content.ends_with('-');
+        if continues {
+            content.pop();
+        }
+
+        if continuing {
+            result.last_mut().unwrap().1.push_str(&content);
+        } else {
+            result.push((i, content));
+        }
+        continuing = continues;
+    }
+    result
+}
+
+fn parse_v3000(lines: &[(usize, &str)]) -> Result<(Vec<MolAtom>, Vec<MolBond>), ParseError> {
+    let mut atoms = Vec::new();
+    let mut bonds = Vec::new();
+    // V3000 atoms have arbitrary index labels, which bonds refer to.
+    let mut atom_ids = Vec::new();
+    let mut block = "";
+
+    for (i, line) in v3000_lines(lines) {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match (tokens.get(0), tokens.get(1)) {
+            (Some(&"BEGIN"), Some(&"ATOM")) => block = "atom",
+            (Some(&"BEGIN"), Some(&"BOND")) => block = "bond",
+            (Some(&"END"), _) => block = "",
+            _ if block == "atom" => {
+                if tokens.len() < 5 {
+                    return Err(ParseError::syntax(i, "Expected `index type x y z`"));
+                }
+                atom_ids.push(parse_num::<usize>(tokens[0], i, "atom index")?);
+                let mut atom = MolAtom {
+                    element: normalize_symbol(tokens[1]),
+                    position: [parse_num(tokens[2], i, "x coordinate")?,
+                               parse_num(tokens[3], i, "y coordinate")?,
+                               parse_num(tokens[4], i, "z coordinate")?],
+                    charge: 0,
+                    mass_number: None,
+                };
+                for prop in &tokens[5..] {
+                    if prop.starts_with("CHG=") {
+                        atom.charge = parse_num(&prop[4..], i, "charge")?;
+                    } else if prop.starts_with("MASS=") {
+                        atom.mass_number = Some(parse_num(&prop[5..], i, "isotope")?);
+                    }
+                }
+                atoms.push(atom);
+            },
+            _ if block == "bond" => {
+                if tokens.len() < 4 {
+                    return Err(ParseError::syntax(i, "Expected `index type atom1 atom2`"));
+                }
+                let mut ends = [0; 2];
+                for j in 0..2 {
+                    let id: usize = parse_num(tokens[j + 2], i, "bond atom")?;
+                    ends[j] = atom_ids.iter().position(|a| *a == id)
+                        .ok_or_else(|| ParseError::syntax(i, "Bond refers to a nonexistent atom"))?;
+                }
+                let stereo = tokens[4..].iter().find(|t| t.starts_with("CFG="))
+                    .and_then(|t| t[4..].parse().ok()).map_or(0, stereo_from_cfg);
+                bonds.push(MolBond {
+                    atoms: (ends[0], ends[1]),
+                    order: bond_order(parse_num(tokens[1], i, "bond type")?),
+                    stereo,
+                });
+            },
+            _ => (),
+        }
+    }
+
+    Ok((atoms, bonds))
+}
+
+fn parse_record(lines: &[(usize, &str)]) -> Result<MolRecord, ParseError> {
+    // `lines` covers one record, up to (not including) its `$$$$` line.
+    if lines.len() < 4 {
+        let line = lines.last().map(|l| l.0).unwrap_or(1);
+        return Err(ParseError::syntax(line, "Expected a 3-line header and a counts line"));
+    }
+
+    let counts = lines[3];
+    let (atoms, bonds) = if counts.1.contains("V3000") {
+        parse_v3000(&lines[4..])?
+    } else {
+        parse_v2000(&lines[4..], counts)?
+    };
+
+    // SDF data fields follow `M  END`: a `> <NAME>` header, value lines, then a
+    // blank line.
+    let mut data = Vec::new();
+    let end = lines.iter().position(|l| l.1.starts_with("M  END")).unwrap_or(lines.len());
+    let mut field: Option<(String, Vec<&str>)> = None;
+
+    for &(_, line) in lines.iter().skip(end + 1) {
+        if line.starts_with('>') {
+            let name = match (line.find('<'), line.rfind('>')) {
+                (Some(start), Some(stop)) if stop > start => line[start + 1..stop].to_string(),
+                _ => String::new(),
+            };
+            field = Some((name, Vec::new()));
+        } else if line.trim().is_empty() {
+            if let Some((name, vals)) = field.take() {
+                data.push((name, vals.join("\n")));
+            }
+        } else if let Some((_, ref mut vals)) = field {
+            vals.push(line);
+        }
+    }
+    if let Some((name, vals)) = field {
+        data.push((name, vals.join("\n")));
+    }
+
+    Ok(MolRecord {
+        name: lines[0].1.trim().to_string(),
+        program: lines[1].1.trim_end().to_string(),
+        comment: lines[2].1.trim_end().to_string(),
+        atoms,
+        bonds,
+        data,
+    })
+}
+
+pub fn parse_sdf(text: &str) -> Result<Vec<MolRecord>, ParseError> {
+    // Also reads single molfiles, which are an SDF with one record.
+    let mut records = Vec::new();
+    let mut current: Vec<(usize, &str)> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        if line.starts_with("$$$$") {
+            records.push(parse_record(&current)?);
+            current.clear();
+        } else {
+            current.push((i + 1, line));
+        }
+    }
+
+    // A molfile, or an SDF missing its final `$$$$`.
+    if current.iter().any(|l| !l.1.trim().is_empty()) {
+        records.push(parse_record(&current)?);
+    }
+
+    Ok(records)
+}
+
+pub fn parse_mol(text: &str) -> Result<MolRecord, ParseError> {
+    parse_sdf(text)?.into_iter().next().ok_or_else(|| ParseError::syntax(1, "Empty file"))
+}
+
+pub fn load(path: &Path) -> Result<Vec<MolRecord>, ParseError> {
+    parse_sdf(&fs::read_to_string(path)?)
+}
+
+pub fn write_mol(record: &MolRecord) -> String {
+    // Write a V2000 molfile, or V3000 if there are too many atoms or bonds to
+    // fit V2000's 3-digit counts.
+    let header = format!("{}\n{}\n{}\n", record.name, record.program, record.comment);
+    if record.atoms.len() > 999 || record.bonds.len() > 999 {
+        header + &v3000_ctab(record)
+    } else {
+        header + &v2000_ctab(record)
+    }
+}
+
+fn v3000_ctab(record: &MolRecord) -> String {
+    let mut result = "  0  0  0     0  0            999 V3000\nM  V30 BEGIN CTAB\n".to_string();
+    result.push_str(&format!("M  V30 COUNTS {} {} 0 0 0\nM  V30 BEGIN ATOM\n",
+                             record.atoms.len(), record.bonds.len()));
+
+    for (i, atom) in record.atoms.iter().enumerate() {
+        result.push_str(&format!("M  V30 {} {} {:.4} {:.4} {:.4} 0", i + 1, atom.element,
+                                 atom.position[0], atom.position[1], atom.position[2]));
+        if atom.charge != 0 {
+            result.push_str(&format!(" CHG={}", atom.charge));
+        }
+        if let Some(mass) = atom.mass_number {
+            result.push_str(&format!(" MASS={}", mass));
+        }
+        result.push('\n');
+    }
+
+    result.push_str("M  V30 END ATOM\nM  V30 BEGIN BOND\n");
+    for (i, bond) in record.bonds.iter().enumerate() {
+        result.push_str(&format!("M  V30 {} {} {} {}", i + 1, bond_code(bond.order),
+                                 bond.atoms.0 + 1, bond.atoms.1 + 1));
+        if stereo_cfg(bond.stereo) != 0 {
+            result.push_str(&format!(" CFG={}", stereo_cfg(bond.stereo)));
+        }
+        result.push('\n');
+    }
+
+    result.push_str("M  V30 END BOND\nM  V30 END CTAB\nM  END\n");
+    result
+}
+
+fn v2000_ctab(record: &MolRecord) -> String {
+    let mut result = format!("{:3}{:3}  0  0  0  0  0  0  0  0999 V2000\n",
+                             record.atoms.len(), record.bonds.len());
+
+    for atom in &record.atoms {
+        result.push_str(&format!(
+            "{:10.4}{:10.4}{:10.4} {:<3} 0{:3}  0  0  0  0  0  0  0  0  0  0\n",
+            atom.position[0], atom.position[1], atom.position[2], atom.element,
+            charge_code(atom.charge)
+        ));
+    }
+
+    for bond in &record.bonds {
+        result.push_str(&format!("{:3}{:3}{:3}{:3}\n", bond.atoms.0 + 1, bond.atoms.1 + 1,
+                                 bond_code(bond.order), bond.stereo));
+    }
+
+    // Property lines hold at most 8 entries each.
+    let charged: Vec<(usize, i8)> = record.atoms.iter().enumerate()
+        .filter(|(_, a)| a.charge != 0).map(|(i, a)| (i + 1, a.charge)).collect();
+    for chunk in charged.chunks(8) {
+        result.push_str(&format!("M  CHG{:3}", chunk.len()));
+        for (i, charge) in chunk { result.push_str(&format!(" {:3} {:3}", i, charge)) }
+        result.push('\n');
+    }
+
+    let isotopes: Vec<(usize, u16)> = record.atoms.iter().enumerate()
+        .filter_map(|(i, a)| a.mass_number.map(|m| (i + 1, m))).collect();
+    for chunk in isotopes.chunks(8) {
+        result.push_str(&format!("M  ISO{:3}", chunk.len()));
+        for (i, mass) in chunk { result.push_str(&format!(" {:3} {:3}", i, mass)) }
+        result.push('\n');
+    }
+
+    result.push_str("M  END\n");
+    result
+}
+
+pub fn write_sdf(records: &[MolRecord]) -> String {
+    let mut result = String::new();
+    for record in records {
+        result.push_str(&write_mol(record));
+        for (name, val) in &record.data {
+            result.push_str(&format!("> <{}>\n{}\n\n", name, val));
+        }
+        result.push_str("$$$$\n");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACETATE: &str = "\
+acetate
+  test
+
+  4  3  0  0  0  0  0  0  0  0999 V2000
+    0.0000    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0
+    1.5000    0.0000    0.0000 C   0  0  0  0  0  0  0  0  0  0  0  0
+    2.2000    1.1000    0.0000 O   0  0  0  0  0  0  0  0  0  0  0  0
+    2.2000   -1.1000    0.0000 O   0  5  0  0  0  0  0  0  0  0  0  0
+  1  2  1  0
+  2  3  2  0
+  2  4  1  0
+M  ISO  1   1  13
+M  END
+> <ID>
+42
+
+> <NOTE>
+two
+lines
+
+$$$$
+";
+
+    #[test]
+    fn v2000_sdf_round_trip() {
+        let records = parse_sdf(ACETATE).unwrap();
+        assert_eq!(records.len(), 1);
+        let r = &records[0];
+        assert_eq!(r.name, "acetate");
+        assert_eq!(r.atoms.len(), 4);
+        assert_eq!(r.atoms[3].charge, -1);
+        assert_eq!(r.atoms[0].mass_number, Some(13));
+        assert_eq!(r.bonds[1].atoms, (1, 2));
+        assert_eq!(r.bonds[1].order, BondOrder::Double);
+        assert_eq!(r.data, vec![("ID".to_string(), "42".to_string()),
+                                ("NOTE".to_string(), "two\nlines".to_string())]);
+
+        let reread = parse_sdf(&write_sdf(&records)).unwrap();
+        assert_eq!(reread.len(), 1);
+        let r2 = &reread[0];
+        assert_eq!(r2.atoms.len(), 4);
+        assert_eq!(r2.atoms[2].position, [2.2, 1.1, 0.]);
+        assert_eq!(r2.atoms[3].charge, -1);
+        assert_eq!(r2.atoms[0].mass_number, Some(13));
+        assert_eq!(r2.bonds[1].order, BondOrder::Double);
+        assert_eq!(r2.data, r.data);
+    }
+
+    #[test]
+    fn v3000() {
+        let text = "\
+cation
+
+
+  0  0  0     0  0            999 V3000
+M  V30 BEGIN CTAB
+M  V30 COUNTS 2 1 0 0 0
+M  V30 BEGIN ATOM
+M  V30 10 N 0.0 0.0 0.0 0 CHG=1
+M  V30 20 C 1.47 0.0 -
+M  V30 0.0 0 MASS=13
+M  V30 END ATOM
+M  V30 BEGIN BOND
+M  V30 1 3 20 10
+M  V30 END BOND
+M  V30 END CTAB
+M  END
+";
+        let r = parse_mol(text).unwrap();
+        assert_eq!(r.atoms.len(), 2);
+        assert_eq!(r.atoms[0].charge, 1);
+        assert_eq!(r.atoms[1].position, [1.47, 0., 0.]);
+        assert_eq!(r.atoms[1].mass_number, Some(13));
+        assert_eq!(r.bonds[0].atoms, (1, 0));
+        assert_eq!(r.bonds[0].order, BondOrder::Triple);
+
+        // Too many atoms for V2000's counts line; written as V3000.
+        let mut chain = MolRecord {
+            name: "chain".to_string(), program: String::new(), comment: String::new(),
+            atoms: (0..1200).map(|i| MolAtom {
+                element: "C".to_string(), position: [i as f32 * 1.5, 0., 0.], charge: 0, mass_number: None,
+            }).collect(),
+            bonds: (0..1199).map(|i| MolBond { atoms: (i, i + 1), order: BondOrder::Single, stereo: 0 }).collect(),
+            data: Vec::new(),
+        };
+        chain.atoms[1100].charge = -1;
+        chain.atoms[1100].mass_number = Some(13);
+        chain.bonds[1000].stereo = 6;
+        let text = write_mol(&chain);
+        assert!(text.lines().nth(3).unwrap().ends_with("V3000"));
+        let r = parse_mol(&text).unwrap();
+        assert_eq!((r.atoms.len(), r.bonds.len()), (1200, 1199));
+        assert_eq!(r.atoms[1199].position, [1798.5, 0., 0.]);
+        assert_eq!((r.atoms[1100].charge, r.atoms[1100].mass_number), (-1, Some(13)));
+        assert_eq!(r.bonds[1000].atoms, (1000, 1001));
+        assert_eq!(r.bonds[1000].stereo, 6);
+    }
+}
@@ -0,0 +1,225 @@
+// Chemistry-level description of a molecule, independent of how it's drawn.
+// File readers convert into this, and scenes are built from it.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use bonding;
+use mol::{self, MolAtom, MolBond, MolRecord};
+use pdb::{self, PdbStructure};
+use types::{BondOrder, ParseError};
+use xyz::{self, XyzFrame};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Residue {
+    pub name: String,  // eg "ALA"
+    pub seq: i32,
+    pub chain: char,
+}
+
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub element: String,  // Symbol, eg "C"
+    pub isotope: Option<u16>,  // Mass number, for isotope-labeled atoms only.
+    pub charge: i8,  // Formal charge
+    pub position: [f32; 3],  // Å
+    pub label: String,  // Atom name, eg "CA" in a PDB file. May be empty.
+    pub residue: Option<Residue>,
+    pub hetero: bool,  // PDB HETATM; eg ligands and solvent.
+}
+
+impl Atom {
+    pub fn new(element: &str, position: [f32; 3]) -> Self {
+        Self {
+            element: element.to_string(),
+            isotope: None,
+            charge: 0,
+            position,
+            label: String::new(),
+            residue: None,
+            hetero: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Bond {
+    pub atoms: (usize, usize),  // Indices into the molecule's atoms.
+    pub order: BondOrder,
+}
+
+impl Bond {
+    pub fn new(a: usize, b: usize, order: BondOrder) -> Self {
+        Self { atoms: (a, b), order }
+    }
+
+    pub fn is_aromatic(&self) -> bool {
+        self.order == BondOrder::Aromatic
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Molecule {
+    pub name: String,
+    pub atoms: Vec<Atom>,
+    pub bonds: Vec<Bond>,
+}
+
+impl Molecule {
+    pub fn new(name: &str, atoms: Vec<Atom>, bonds: Vec<Bond>) -> Self {
+        Self { name: name.to_string(), atoms, bonds }
+    }
+
+    pub fn centroid(&self) -> [f32; 3] {
+        let n = self.atoms.len().max(1) as f32;
+        let mut center = [0., 0., 0.];
+        for atom in &self.atoms {
+            for i in 0..3 { center[i] += atom.position[i] / n }
+        }
+        center
+    }
+
+    pub fn neighbors(&self, atom: usize) -> Vec<usize> {
+        self.bonds.iter().filter_map(|b| {
+            if b.atoms.0 == atom { Some(b.atoms.1) }
+            else if b.atoms.1 == atom { Some(b.atoms.0) }
+            else { None }
+        }).collect()
+    }
+
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        // Each atom's neighbors, by index; faster than `neighbors` for many atoms.
+        let mut result = vec![Vec::new(); self.atoms.len()];
+        for bond in &self.bonds {
+            result[bond.atoms.0].push(bond.atoms.1);
+            result[bond.atoms.1].push(bond.atoms.0);
+        }
+        result
+    }
+
+    pub fn perceive_bonds(&mut self) {
+        // Add bonds inferred from interatomic distances, keeping any we already
+        // have, eg from CONECT records.
+        let existing: HashSet<(usize, usize)> = self.bonds.iter()
+            .map(|b| (b.atoms.0.min(b.atoms.1), b.atoms.0.max(b.atoms.1))).collect();
+
+        for bond in bonding::perceive_bonds(&self.atoms, bonding::DEFAULT_TOLERANCE) {
+            if !existing.contains(&bond.atoms) {
+                self.bonds.push(bond);
+            }
+        }
+    }
+
+    pub fn from_pdb(structure: &PdbStructure, model: usize) -> Option<Self> {
+        // None if there's no such model. Only the first alternate location of
+        // each atom is kept. CONECT bonds whose atoms aren't in this model are
+        // skipped. CONECT records usually only cover hetero groups, so we infer
+        // the rest from distances.
+        let model = structure.models.get(model)?.without_alt_locs();
+
+        let atoms = model.atoms.iter().map(|a| Atom {
+            element: a.element.clone(),
+            isotope: None,
+            charge: a.charge,
+            position: a.position,
+            label: a.name.clone(),
+            residue: Some(Residue { name: a.res_name.clone(), seq: a.res_seq, chain: a.chain_id }),
+            hetero: a.hetero,
+        }).collect();
+
+        let index: HashMap<u32, usize> = model.atoms.iter().enumerate()
+            .map(|(i, a)| (a.serial, i)).collect();
+        let bonds = structure.bonds.iter().filter_map(|(a, b)| {
+            match (index.get(a), index.get(b)) {
+                (Some(i), Some(j)) => Some(Bond::new(*i, *j, BondOrder::Single)),
+                _ => None,
+            }
+        }).collect();
+
+        let mut result = Self::new(&structure.title, atoms, bonds);
+        result.perceive_bonds();
+        Some(result)
+    }
+
+    pub fn to_mol_record(&self) -> MolRecord {
+        MolRecord {
+            name: self.name.clone(),
+            program: "  chemgraphics".to_string(),
+            comment: String::new(),
+            atoms: self.atoms.iter().map(|a| MolAtom {
+                element: a.element.clone(),
+                position: a.position,
+                charge: a.charge,
+                mass_number: a.isotope,
+            }).collect(),
+            bonds: self.bonds.iter().map(|b| MolBond { atoms: b.atoms, order: b.order, stereo: 0 })
+                .collect(),
+            data: Vec::new(),
+        }
+    }
+}
+
+impl<'a> From<&'a XyzFrame> for Molecule {
+    fn from(frame: &XyzFrame) -> Self {
+        // XYZ files have no connectivity; infer it.
+        let atoms = frame.atoms.iter().map(|a| Atom::new(&a.element, a.position)).collect();
+        let mut result = Self::new(&frame.comment, atoms, Vec::new());
+        result.perceive_bonds();
+        result
+    }
+}
+
+impl<'a> From<&'a MolRecord> for Molecule {
+    fn from(record: &MolRecord) -> Self {
+        let atoms = record.atoms.iter().map(|a| Atom {
+            isotope: a.mass_number,
+            charge: a.charge,
+            ..Atom::new(&a.element, a.position)
+        }).collect();
+        let bonds = record.bonds.iter().map(|b| Bond::new(b.atoms.0, b.atoms.1, b.order)).collect();
+
+        Self::new(&record.name, atoms, bonds)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Molecule, ParseError> {
+    // Load the first molecule (frame, model, or record) in a file, detecting its
+    // format from the extension.
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+
+    let result = match ext.as_ref() {
+        "xyz" => xyz::load(path)?.first().map(Molecule::from),
+        "pdb" | "ent" => Molecule::from_pdb(&pdb::load(path)?, 0),
+        "mol" | "sdf" | "sd" => mol::load(path)?.first().map(Molecule::from),
+        _ => return Err(ParseError::UnknownFormat(ext)),
+    };
+
+    result.ok_or_else(|| ParseError::syntax(1, "No molecules in file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_pdb_maps_conect_serials() {
+        let text = "\
+ATOM     10  N   GLY A   5       0.000   0.000   0.000  1.00  0.00           N
+ATOM     11  CA  GLY A   5       1.450   0.000   0.000  1.00  0.00           C
+HETATM   20  O   HOH B   9       5.000   5.000   5.000  1.00  0.00           O
+CONECT   10   11
+CONECT   11   99
+";
+        let structure = pdb::parse(text).unwrap();
+        assert!(Molecule::from_pdb(&structure, 1).is_none());
+        let m = Molecule::from_pdb(&structure, 0).unwrap();
+        assert_eq!(m.atoms.len(), 3);
+        assert_eq!(m.atoms[1].label, "CA");
+        assert_eq!(m.atoms[1].residue, Some(Residue { name: "GLY".to_string(), seq: 5, chain: 'A' }));
+        assert!(m.atoms[2].hetero);
+        // Serial 99 doesn't exist, so only one bond.
+        assert_eq!(m.bonds.len(), 1);
+        assert_eq!(m.bonds[0].atoms, (0, 1));
+        assert_eq!(m.neighbors(1), vec![0]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::cmp::Ordering;
+
 use num;
 
 // Operators; For manipulating vectors and matrices that use built-in formats like array.
@@ -7,6 +9,10 @@ pub fn add_arr(arr1: &[f32; 3], arr2: &[f32; 3]) -> [f32; 3] {
     [arr1[0] + arr2[0], arr1[1] + arr2[1], arr1[2] + arr2[2]]
 }
 
+pub fn sub_arr(arr1: &[f32; 3], arr2: &[f32; 3]) -> [f32; 3] {
+    [arr1[0] - arr2[0], arr1[1] - arr2[1], arr1[2] - arr2[2]]
+}
+
 pub fn mul_arr(arr: &[f32; 3], val: f32) -> [f32; 3] {
     [arr[0] * val, arr[1] * val, arr[2] * val]
 }
@@ -19,6 +25,25 @@ pub fn div_arr4(arr: &[f32; 4], val: f32) -> [f32; 4] {
     [arr[0] / val, arr[1] / val, arr[2] / val, arr[3] / val]
 }
 
+pub fn dot3(v0: &[f32; 3], v1: &[f32; 3]) -> f32 {
+    v0[0] * v1[0] + v0[1] * v1[1] + v0[2] * v1[2]
+}
+
+pub fn cross(v0: &[f32; 3], v1: &[f32; 3]) -> [f32; 3] {
+    [
+        v0[1] * v1[2] - v0[2] * v1[1],
+        v0[2] * v1[0] - v0[0] * v1[2],
+        v0[0] * v1[1] - v0[1] * v1[0],
+    ]
+}
+
+pub fn normalize(v: &[f32; 3]) -> [f32; 3] {
+    // Zero vectors are returned unchanged.
+    let len = dot3(v, v).sqrt();
+    if len == 0. { return *v }
+    mul_arr(v, 1. / len)
+}
+
 pub fn transpose(M: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
     [
         [M[0][0], M[1][0], M[2][0], M[3][0]],
@@ -84,6 +109,45 @@ pub fn dot_v(M: &[[f32; 4]; 4], v: [f32; 4]) -> [f32; 4] {
     ]
 }
 
+pub fn inverse(M: [[f32; 4]; 4]) -> Option<[[f32; 4]; 4]> {
+    // Invert a len-4 matrix by Gauss-Jordan elimination, with partial pivoting.
+    // Returns None for singular matrices, and ones with infinite or NaN entries.
+    let mut A = M;
+    let mut result = [
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 0., 0., 1.],
+    ];
+
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&i, &j| {
+            A[i][col].abs().partial_cmp(&A[j][col].abs()).unwrap_or(Ordering::Equal)
+        })?;
+        if !A[pivot][col].is_finite() || A[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        A.swap(col, pivot);
+        result.swap(col, pivot);
+
+        let p = A[col][col];
+        for j in 0..4 {
+            A[col][j] /= p;
+            result[col][j] /= p;
+        }
+
+        for i in 0..4 {
+            if i == col { continue }
+            let factor = A[i][col];
+            for j in 0..4 {
+                A[i][j] -= factor * A[col][j];
+                result[i][j] -= factor * result[col][j];
+            }
+        }
+    }
+    Some(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -113,4 +177,27 @@ mod tests {
 
         assert_eq!(dot(a, b), expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn inverse_A() {
+        let a = [
+            [2., 0., 0., 1.],
+            [0., 1., 3., 0.],
+            [0., 0., 1., 0.],
+            [1., 0., 0., 1.],
+        ];
+        let id = dot(a, inverse(a).unwrap());
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((id[i][j] - if i == j { 1. } else { 0. }).abs() < 1e-6);
+            }
+        }
+
+        // Singular, and not finite.
+        let mut b = a;
+        b[3] = b[0];
+        assert_eq!(inverse(b), None);
+        b[3] = [::std::f32::NAN, 0., 0., ::std::f32::INFINITY];
+        assert_eq!(inverse(b), None);
+    }
+}
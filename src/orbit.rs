@@ -0,0 +1,183 @@
+// Orbit (arcball) camera control: the camera circles a target point at a set
+// distance, always facing it. This is how molecular viewers usually work; drag
+// to rotate the molecule, scroll to dolly, and pan to move the target.
+
+use std::collections::HashMap;
+
+use ops::{add_arr, cross, dot3, mul_arr, normalize};
+use types::{Camera, Orbit, Quaternion, Scene, Shape};
+
+// Keep the camera from reaching, or passing through, its target.
+const MIN_DISTANCE: f32 = 0.5;
+
+pub fn sync(cam: &mut Camera, orbit: &Orbit) {
+    // Place the camera `distance` behind the target, along its view direction.
+    cam.position = add_arr(&orbit.target, &mul_arr(&cam.forward(), -orbit.distance));
+}
+
+pub fn rotate(cam: &mut Camera, orbit: &Orbit, axis: &[f32; 3], angle: f32) {
+    // Rotate about one of the camera's axes, as with a free camera, then move to
+    // keep facing the target.
+    cam.orientation = (cam.orientation * Quaternion::from_axis_angle(axis, angle)).normalize();
+    sync(cam, orbit);
+}
+
+fn arcball_point(pt: [f32; 2]) -> [f32; 3] {
+    // Map a point on the screen to a unit sphere facing the camera, in camera
+    // space. Points outside the sphere's silhouette map onto a hyperbolic sheet,
+    // so drags there rotate smoothly about the view axis.
+    let d = pt[0].powi(2) + pt[1].powi(2);
+    let z = if d <= 0.5 { (1. - d).sqrt() } else { 0.5 / d.sqrt() };
+    // The camera looks along +z, so the sphere's visible side faces -z.
+    normalize(&[pt[0], pt[1], -z])
+}
+
+pub fn arcball(cam: &mut Camera, orbit: &Orbit, from: [f32; 2], to: [f32; 2]) {
+    // Rotate the view for a drag between two points on the screen. Points are in
+    // normalized device coordinates, but with y up: x and y run from -1 to 1,
+    // left to right, and bottom to top. The point under the cursor follows it,
+    // as if dragging the molecule itself.
+    let (p0, p1) = (arcball_point(from), arcball_point(to));
+
+    let axis = cross(&p0, &p1);
+    if dot3(&axis, &axis) < 1e-12 {
+        return;
+    }
+    let angle = dot3(&p0, &p1).max(-1.).min(1.).acos();
+
+    // Rotating the molecule one way is rotating the camera around it the other.
+    let drag = Quaternion::from_axis_angle(&axis, angle);
+    cam.orientation = (cam.orientation * drag.conjugate()).normalize();
+    sync(cam, orbit);
+}
+
+pub fn dolly(cam: &mut Camera, orbit: &mut Orbit, amount: f32) {
+    // Move towards the target; negative amounts move away. Scaled by distance,
+    // so each scroll step covers the same fraction of the way in.
+    orbit.distance = (orbit.distance * (1. - amount)).max(MIN_DISTANCE);
+    sync(cam, orbit);
+}
+
+pub fn pan(cam: &mut Camera, orbit: &mut Orbit, dx: f32, dy: f32) {
+    // Move the target and camera together, across the screen. dx and dy are
+    // fractions of the view's half-height at the target, so a pan of 1 moves
+    // a point at the center to the top edge.
+    let half_height = orbit.distance * (cam.fov / 2.).tan();
+    let offset = add_arr(&mul_arr(&cam.right(), dx * half_height), &mul_arr(&cam.up(), dy * half_height));
+
+    orbit.target = add_arr(&orbit.target, &offset);
+    sync(cam, orbit);
+}
+
+pub fn bounding_sphere(shapes: &HashMap<u32, Shape>) -> ([f32; 3], f32) {
+    // A sphere containing all shapes' vertices, centered on the average of the
+    // shapes' positions. Not the smallest such sphere, but close for molecules.
+    if shapes.is_empty() {
+        return ([0., 0., 0.], 0.);
+    }
+
+    let n = shapes.len() as f32;
+    let center = shapes.values().fold([0., 0., 0.], |acc, s| add_arr(&acc, &mul_arr(&s.position, 1. / n)));
+
+    let radius = shapes.values().flat_map(|shape| {
+        let rotation = Quaternion::from_euler(&shape.orientation);
+        shape.mesh.vertices.values().map(move |v| {
+            let p = add_arr(&rotation.rotate_vec(&v.position), &shape.position);
+            let d = [p[0] - center[0], p[1] - center[1], p[2] - center[2]];
+            dot3(&d, &d).sqrt()
+        })
+    }).fold(0., f32::max);
+
+    (center, radius)
+}
+
+pub fn center_on(cam: &mut Camera, orbit: &mut Orbit, points: &[[f32; 3]]) {
+    // Make the average of `points` the target, keeping the distance.
+    if points.is_empty() {
+        return;
+    }
+    let n = points.len() as f32;
+    orbit.target = points.iter().fold([0., 0., 0.], |acc, p| add_arr(&acc, &mul_arr(p, 1. / n)));
+    sync(cam, orbit);
+}
+
+pub fn fit(cam: &mut Camera, orbit: &mut Orbit, center: [f32; 3], radius: f32) {
+    // Target a sphere, and back off until it fills the view. The far plane's
+    // pushed back if needed, so the sphere isn't clipped.
+    let half_fov = (cam.fov / 2.).min((cam.fov / 2.).tan().atan2(1. / cam.aspect));
+
+    orbit.target = center;
+    orbit.distance = (radius / half_fov.sin()).max(MIN_DISTANCE);
+    cam.far = cam.far.max(orbit.distance + radius * 2.);
+    sync(cam, orbit);
+}
+
+pub fn fit_all(scene: &mut Scene) {
+    // Fit every shape in the scene in view.
+    let (center, radius) = bounding_sphere(&scene.shapes);
+    fit(&mut scene.cam, &mut scene.orbit, center, radius);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+    use scenes;
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        (a[0] - b[0]).abs() < 1e-4 && (a[1] - b[1]).abs() < 1e-4 && (a[2] - b[2]).abs() < 1e-4
+    }
+
+    #[test]
+    fn orbit() {
+        let mut scene = scenes::scene_1(1.);
+        let orbit = Orbit { target: [1., 2., 3.], distance: 5. };
+
+        // The camera stays at its distance, facing the target, however it's rotated.
+        rotate(&mut scene.cam, &orbit, &[0., 1., 0.], PI / 2.);
+        rotate(&mut scene.cam, &orbit, &[1., 0., 0.], 1.);
+        let to_target = [1. - scene.cam.position[0], 2. - scene.cam.position[1], 3. - scene.cam.position[2]];
+        assert!((dot3(&to_target, &to_target).sqrt() - 5.).abs() < 1e-4);
+        assert!(close(normalize(&to_target), scene.cam.forward()));
+
+        // Dragging right from the center swings the camera left around the target,
+        // so the molecule appears to turn right.
+        let mut scene = scenes::scene_1(1.);
+        let orbit = Orbit { target: [0., 0., 0.], distance: 7. };
+        arcball(&mut scene.cam, &orbit, [0., 0.], [0.5, 0.]);
+        assert!(scene.cam.position[0] < -1.);
+        assert!(scene.cam.position[1].abs() < 1e-4);
+    }
+
+    #[test]
+    fn fit_and_dolly() {
+        let mut scene = scenes::scene_1(1.);
+        fit_all(&mut scene);
+
+        // Each nucleus is within the view frustum.
+        let (center, radius) = bounding_sphere(&scene.shapes);
+        assert!(radius > 4.);
+        assert!(close(scene.orbit.target, center));
+        let half_fov = scene.cam.fov / 2.;
+        for shape in scene.shapes.values() {
+            let d = [shape.position[0] - scene.cam.position[0], shape.position[1] - scene.cam.position[1],
+                     shape.position[2] - scene.cam.position[2]];
+            let angle = (dot3(&d, &scene.cam.forward()) / dot3(&d, &d).sqrt()).acos();
+            assert!(angle < half_fov);
+        }
+
+        let distance = scene.orbit.distance;
+        dolly(&mut scene.cam, &mut scene.orbit, 0.5);
+        assert!((scene.orbit.distance - distance / 2.).abs() < 1e-4);
+        dolly(&mut scene.cam, &mut scene.orbit, 1.);
+        assert_eq!(scene.orbit.distance, MIN_DISTANCE);
+
+        // Panning moves the camera and target together.
+        let (target, position) = (scene.orbit.target, scene.cam.position);
+        pan(&mut scene.cam, &mut scene.orbit, 1., 0.);
+        let moved = [scene.orbit.target[0] - target[0], scene.orbit.target[1] - target[1],
+                     scene.orbit.target[2] - target[2]];
+        assert!(close(add_arr(&position, &moved), scene.cam.position));
+        assert!(dot3(&moved, &scene.cam.right()) > 0.);
+    }
+}
@@ -0,0 +1,251 @@
+// Reads Protein Data Bank (PDB) files. We handle the records needed to draw
+// a structure: ATOM/HETATM, CONECT, MODEL/ENDMDL, and TITLE. Other records are
+// skipped. PDB is a fixed-column format; column numbers below are 1-based,
+// as in the spec: http://www.wwpdb.org/documentation/file-format
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use elements;
+use types::ParseError;
+use xyz::normalize_symbol;
+
+#[derive(Clone, Debug)]
+pub struct PdbAtom {
+    pub serial: u32,
+    pub name: String,  // eg "CA"
+    pub alt_loc: Option<char>,
+    pub res_name: String,  // eg "ALA"
+    pub chain_id: char,
+    pub res_seq: i32,
+    pub i_code: Option<char>,  // Residue insertion code.
+    pub position: [f32; 3],  // Å
+    pub occupancy: f32,
+    pub temp_factor: f32,
+    pub element: String,
+    pub charge: i8,
+    pub hetero: bool,  // True for HETATM records; eg ligands and water.
+}
+
+#[derive(Clone, Debug)]
+pub struct PdbModel {
+    pub atoms: Vec<PdbAtom>,
+}
+
+impl PdbModel {
+    pub fn without_alt_locs(&self) -> PdbModel {
+        // Keep only the first alternate location listed for each atom; this is
+        // what you usually want to draw.
+        let mut seen = HashSet::new();
+        let atoms = self.atoms.iter().filter(|a| {
+            a.alt_loc.is_none() ||
+                seen.insert((a.chain_id, a.res_seq, a.i_code, a.name.clone()))
+        }).cloned().collect();
+
+        PdbModel { atoms }
+    }
+
+    pub fn index_of(&self, serial: u32) -> Option<usize> {
+        self.atoms.iter().position(|a| a.serial == serial)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PdbStructure {
+    pub title: String,
+    // Files without MODEL records have a single model.
+    pub models: Vec<PdbModel>,
+    // Pairs of atom serial numbers, from CONECT records. Each bond is listed once,
+    // lower serial first. These apply to every model.
+    pub bonds: Vec<(u32, u32)>,
+}
+
+fn col(line: &str, start: usize, end: usize) -> &str {
+    // Columns `start` through `end`, inclusive and 1-based, trimmed. Lines are often
+    // shorter than the full record, so missing columns are empty.
+    let end = end.min(line.len());
+    if start > end {
+        return "";
+    }
+    line.get(start - 1..end).unwrap_or("").trim()
+}
+
+fn col_char(line: &str, i: usize) -> Option<char> {
+    col(line, i, i).chars().next()
+}
+
+fn parse_charge(text: &str) -> i8 {
+    // Charges are written as eg "2+" or "1-".
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(d), Some(sign)) if d.is_digit(10) => {
+            let val = d.to_digit(10).unwrap() as i8;
+            if sign == '-' { -val } else { val }
+        },
+        _ => 0,
+    }
+}
+
+fn element_from_name(field: &str) -> String {
+    // Old files leave the element columns blank; guess from the atom name
+    // field, columns 13 to 16, untrimmed. Symbols are right-justified in its
+    // first two columns, so "CA  " is calcium, and " CA " is an alpha carbon.
+    // Four-character hydrogen names, eg "HG21", start in column 13 too.
+    let chars: Vec<char> = field.chars().collect();
+    let at = |i: usize| chars.get(i).cloned().unwrap_or(' ');
+
+    let hydrogen = at(0) == 'H' && chars.len() == 4 && !at(3).is_whitespace();
+    if at(0).is_alphabetic() && at(1).is_alphabetic() && !hydrogen {
+        let two: String = chars[..2].iter().collect();
+        if let Some(element) = elements::by_symbol(&two) {
+            return element.symbol.to_string();
+        }
+    }
+
+    // Otherwise, one letter: in column 14 when 13 is blank or a digit.
+    match chars.iter().find(|c| c.is_alphabetic()) {
+        Some(c) => normalize_symbol(&c.to_string()),
+        None => String::new(),
+    }
+}
+
+fn parse_atom(line: &str, line_num: usize) -> Result<PdbAtom, ParseError> {
+    let num = |start, end, label: &str| -> Result<f32, ParseError> {
+        col(line, start, end).parse()
+            .map_err(|_| ParseError::syntax(line_num, &format!("Invalid {}", label)))
+    };
+
+    let name = col(line, 13, 16).to_string();
+    let element = match col(line, 77, 78) {
+        "" => element_from_name(line.get(12..16).or_else(|| line.get(12..)).unwrap_or("")),
+        e => normalize_symbol(e),
+    };
+
+    Ok(PdbAtom {
+        serial: col(line, 7, 11).parse()
+            .map_err(|_| ParseError::syntax(line_num, "Invalid atom serial number"))?,
+        name,
+        alt_loc: col_char(line, 17),
+        res_name: col(line, 18, 20).to_string(),
+        chain_id: col_char(line, 22).unwrap_or(' '),
+        res_seq: col(line, 23, 26).parse().unwrap_or(0),
+        i_code: col_char(line, 27),
+        position: [num(31, 38, "x coordinate")?, num(39, 46, "y coordinate")?,
+                   num(47, 54, "z coordinate")?],
+        occupancy: num(55, 60, "occupancy").unwrap_or(1.),
+        temp_factor: num(61, 66, "temperature factor").unwrap_or(0.),
+        element,
+        charge: parse_charge(col(line, 79, 80)),
+        hetero: line.starts_with("HETATM"),
+    })
+}
+
+pub fn parse(text: &str) -> Result<PdbStructure, ParseError> {
+    let mut title = String::new();
+    let mut models = Vec::new();
+    let mut current = PdbModel { atoms: Vec::new() };
+    let mut bonds = HashSet::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_num = i + 1;
+        match col(line, 1, 6) {
+            "ATOM" | "HETATM" => current.atoms.push(parse_atom(line, line_num)?),
+            "MODEL" => {
+                // Atoms before the first MODEL record, if any, form their own model.
+                if !current.atoms.is_empty() {
+                    models.push(current);
+                }
+                current = PdbModel { atoms: Vec::new() };
+            },
+            "ENDMDL" => {
+                models.push(current);
+                current = PdbModel { atoms: Vec::new() };
+            },
+            "TITLE" => {
+                if !title.is_empty() { title.push(' ') }
+                // Continuation lines carry a number in columns 9-10.
+                title.push_str(col(line, 11, 80));
+            },
+            "CONECT" => {
+                let from: u32 = col(line, 7, 11).parse()
+                    .map_err(|_| ParseError::syntax(line_num, "Invalid CONECT serial number"))?;
+                for start in &[12, 17, 22, 27] {
+                    if let Ok(to) = col(line, *start, start + 4).parse::<u32>() {
+                        bonds.insert(if from < to { (from, to) } else { (to, from) });
+                    }
+                }
+            },
+            "END" => break,
+            _ => (),
+        }
+    }
+
+    if !current.atoms.is_empty() {
+        models.push(current);
+    }
+
+    let mut bonds: Vec<(u32, u32)> = bonds.into_iter().collect();
+    bonds.sort();
+
+    Ok(PdbStructure { title, models, bonds })
+}
+
+pub fn load(path: &Path) -> Result<PdbStructure, ParseError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "\
+TITLE     TEST STRUCTURE
+MODEL        1
+ATOM      1  N   ALA A   1      11.104   6.134  -6.504  1.00  0.00           N
+ATOM      2  CA AALA A   1      11.639   6.071  -5.147  0.50  0.00           C
+ATOM      3  CA BALA A   1      11.700   6.000  -5.100  0.50  0.00           C
+HETATM    4 FE   HEM B   2       1.000   2.000   3.000  1.00 10.00          FE2+
+ENDMDL
+MODEL        2
+ATOM      1  N   ALA A   1      11.000   6.000  -6.000  1.00  0.00
+ENDMDL
+CONECT    1    2    3
+CONECT    2    1
+END
+";
+
+    #[test]
+    fn atoms_models_bonds() {
+        let s = parse(TEXT).unwrap();
+        assert_eq!(s.title, "TEST STRUCTURE");
+        assert_eq!(s.models.len(), 2);
+        assert_eq!(s.models[0].atoms.len(), 4);
+        assert_eq!(s.bonds, vec![(1, 2), (1, 3)]);
+
+        let ca = &s.models[0].atoms[1];
+        assert_eq!(ca.name, "CA");
+        assert_eq!(ca.alt_loc, Some('A'));
+        assert_eq!(ca.res_name, "ALA");
+        assert_eq!(ca.chain_id, 'A');
+        assert_eq!(ca.position, [11.639, 6.071, -5.147]);
+
+        let fe = &s.models[0].atoms[3];
+        assert!(fe.hetero);
+        assert_eq!(fe.element, "Fe");
+        assert_eq!(fe.charge, 2);
+
+        // No element columns; inferred from the name.
+        assert_eq!(s.models[1].atoms[0].element, "N");
+        assert_eq!(element_from_name(" CA "), "C");
+        assert_eq!(element_from_name("CA  "), "Ca");
+        assert_eq!(element_from_name("FE"), "Fe");
+        assert_eq!(element_from_name("ZN  "), "Zn");
+        assert_eq!(element_from_name("1HG1"), "H");
+        assert_eq!(element_from_name("HG21"), "H");
+        assert_eq!(element_from_name("CB  "), "C");
+        assert_eq!(element_from_name("ÉÉ"), "É");
+
+        assert_eq!(s.models[0].without_alt_locs().atoms.len(), 3);
+    }
+}
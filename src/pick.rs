@@ -0,0 +1,207 @@
+// Picking: find the atom under a point on the screen, by casting a ray from the
+// camera through it, and intersecting the ray with atom spheres and bond
+// cylinders, as drawn in the scene's representation.
+
+use molecule::Molecule;
+use ops::{add_arr, dot3, mul_arr, normalize, sub_arr};
+use scenes;
+use transforms;
+use types::{Camera, Representation, Scene};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: [f32; 3],
+    pub direction: [f32; 3],  // Normalized.
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> [f32; 3] {
+        add_arr(&self.origin, &mul_arr(&self.direction, t))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    // For bond hits, `atom` is the end atom nearer the hit, as for a half-colored bond.
+    pub atom: usize,
+    pub bond: Option<usize>,  // Index into the molecule's bonds.
+    pub distance: f32,  // Along the ray.
+    pub point: [f32; 3],
+}
+
+pub fn screen_ray(cam: &Camera, position: [f32; 2], dimensions: [u32; 2]) -> Option<Ray> {
+    // The world-space ray through a pixel, in pixels from the top left. Starts
+    // at the near plane. None for cameras we can't unproject through.
+    let x = 2. * position[0] / dimensions[0] as f32 - 1.;
+    let y = 2. * position[1] / dimensions[1] as f32 - 1.;  // Vulkan's y is down too.
+
+    let near = transforms::unproject(cam, [x, y, 0.])?;
+    let far = transforms::unproject(cam, [x, y, 1.])?;
+    Some(Ray { origin: near, direction: normalize(&sub_arr(&far, &near)) })
+}
+
+pub fn ray_sphere(ray: &Ray, center: &[f32; 3], radius: f32) -> Option<f32> {
+    // Distance along the ray to where it enters the sphere, or 0 if it starts
+    // inside.
+    let oc = sub_arr(&ray.origin, center);
+    let b = dot3(&oc, &ray.direction);
+    let c = dot3(&oc, &oc) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0. {
+        return None;
+    }
+
+    let sqrt_d = discriminant.sqrt();
+    let (t0, t1) = (-b - sqrt_d, -b + sqrt_d);
+    if t1 < 0. {
+        None
+    } else {
+        Some(t0.max(0.))
+    }
+}
+
+pub fn ray_cylinder(ray: &Ray, start: &[f32; 3], end: &[f32; 3], radius: f32) -> Option<f32> {
+    // Distance along the ray to where it enters the side of a cylinder between
+    // two points. The ends are open; atom spheres cap them.
+    let axis = sub_arr(end, start);
+    let length = dot3(&axis, &axis).sqrt();
+    if length == 0. {
+        return None;
+    }
+    let axis = mul_arr(&axis, 1. / length);
+
+    // Work in the plane perpendicular to the axis, where the cylinder's a circle.
+    let perp = |v: &[f32; 3]| sub_arr(v, &mul_arr(&axis, dot3(v, &axis)));
+    let d = perp(&ray.direction);
+    let oc = perp(&sub_arr(&ray.origin, start));
+
+    let a = dot3(&d, &d);
+    if a < 1e-12 {
+        return None;  // Parallel to the axis.
+    }
+    let b = dot3(&oc, &d);
+    let c = dot3(&oc, &oc) - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+
+    let sqrt_d = discriminant.sqrt();
+    [(-b - sqrt_d) / a, (-b + sqrt_d) / a].iter().cloned()
+        .map(|t| t.max(0.))
+        .find(|&t| {
+            let along = dot3(&sub_arr(&ray.at(t), start), &axis);
+            along >= 0. && along <= length && (t > 0. || c <= 0.)
+        })
+}
+
+pub fn pick_ray(molecule: &Molecule, representation: Representation, ray: &Ray) -> Option<Hit> {
+    // The closest atom or bond the ray hits, with the molecule drawn as in
+    // `scenes::molecule_shapes`.
+    let positions = scenes::atom_positions(molecule);
+    let mut closest: Option<Hit> = None;
+
+    let mut consider = |hit: Hit| {
+        if closest.map_or(true, |c| hit.distance < c.distance) {
+            closest = Some(hit);
+        }
+    };
+
+    for (i, atom) in molecule.atoms.iter().enumerate() {
+        let radius = scenes::atom_radius(&atom.element, representation);
+        if let Some(t) = ray_sphere(ray, &positions[i], radius) {
+            consider(Hit { atom: i, bond: None, distance: t, point: ray.at(t) });
+        }
+    }
+
+    if let Some(radius) = scenes::bond_radius(representation) {
+        for (i, bond) in molecule.bonds.iter().enumerate() {
+            let (a, b) = bond.atoms;
+            if let Some(t) = ray_cylinder(ray, &positions[a], &positions[b], radius) {
+                let point = ray.at(t);
+                let (da, db) = (sub_arr(&point, &positions[a]), sub_arr(&point, &positions[b]));
+                let atom = if dot3(&da, &da) <= dot3(&db, &db) { a } else { b };
+                consider(Hit { atom, bond: Some(i), distance: t, point });
+            }
+        }
+    }
+
+    closest
+}
+
+pub fn pick(scene: &Scene, position: [f32; 2], dimensions: [u32; 2]) -> Option<Hit> {
+    // The atom under a pixel, in pixels from the top left. None for scenes
+    // without a molecule.
+    let molecule = scene.molecule.as_ref()?;
+    pick_ray(molecule, scene.representation, &screen_ray(&scene.cam, position, dimensions)?)
+}
+
+pub fn describe(molecule: &Molecule, atom: usize) -> String {
+    // A short label for an atom, eg for hover text: "C12", or with PDB
+    // information, "C12 CA ALA A5".
+    let a = &molecule.atoms[atom];
+    let mut result = format!("{}{}", a.element, atom + 1);
+    if !a.label.is_empty() {
+        result += &format!(" {}", a.label);
+    }
+    if let Some(ref residue) = a.residue {
+        result += &format!(" {} {}{}", residue.name, residue.chain, residue.seq);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use molecule::{Atom, Bond};
+    use types::BondOrder;
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+        (0..3).all(|i| (a[i] - b[i]).abs() < 1e-3)
+    }
+
+    #[test]
+    fn intersections() {
+        let ray = Ray { origin: [0., 0., -5.], direction: [0., 0., 1.] };
+        assert_eq!(ray_sphere(&ray, &[0., 0., 0.], 1.), Some(4.));
+        assert_eq!(ray_sphere(&ray, &[0., 2., 0.], 1.), None);
+        assert_eq!(ray_sphere(&ray, &[0., 0., -10.], 1.), None);  // Behind.
+        assert_eq!(ray_sphere(&ray, &[0., 0., -5.], 1.), Some(0.));  // Inside.
+
+        // A cylinder across the ray, and one that ends short of it.
+        let t = ray_cylinder(&ray, &[-1., 0., 0.], &[1., 0., 0.], 0.5).unwrap();
+        assert!((t - 4.5).abs() < 1e-5);
+        assert_eq!(ray_cylinder(&ray, &[0.5, 0., 0.], &[2., 0., 0.], 0.2), None);
+    }
+
+    #[test]
+    fn pick_atoms() {
+        // H2, along x; drawn centered on the origin.
+        let molecule = Molecule::new("H2", vec![
+            Atom::new("H", [0., 0., 0.]),
+            Atom::new("H", [2., 0., 0.]),
+        ], vec![Bond::new(0, 1, BondOrder::Single)]);
+
+        let mut scene = scenes::from_molecule(1., &molecule, Representation::BallAndStick);
+        let dims = [100, 100];
+
+        // The camera's fitted to the molecule, facing +z; the first atom's on the left.
+        let hit = pick(&scene, [50., 50.], dims).unwrap();
+        assert_eq!(hit.bond, Some(0));
+        assert!(close(hit.point, [0., 0., -scenes::bond_radius(Representation::BallAndStick).unwrap()]));
+
+        let ray = screen_ray(&scene.cam, [50., 50.], dims).unwrap();
+        let left = pick_ray(&molecule, scene.representation, &Ray { origin: add_arr(&ray.origin, &[-1., 0., 0.]), ..ray });
+        assert_eq!(left.map(|h| (h.atom, h.bond)), Some((0, None)));
+        assert_eq!(pick(&scene, [50., 0.], dims), None);
+        let mut zoomed = scene.clone();
+        zoomed.cam.fov = 0.;
+        assert_eq!(pick(&zoomed, [50., 50.], dims), None);
+
+        // Space-filling has no bonds to hit.
+        scenes::set_representation(&mut scene, Representation::SpaceFilling);
+        assert_eq!(pick(&scene, [50., 50.], dims).unwrap().bond, None);
+
+        assert_eq!(describe(&molecule, 1), "H2");
+    }
+}
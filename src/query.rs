@@ -0,0 +1,336 @@
+// Selection expressions: a small language for picking out atoms by their
+// properties, eg `element C and within 5 of resname LIG`, `chain A and backbone`,
+// or `index 10-40`. Evaluates to atom indices, eg for `Scene::selection`.
+//
+// Terms:
+//     all, none
+//     element SYMBOL...      eg element C N O
+//     name NAME...           Atom names, eg name CA CB
+//     resname NAME...        Residue names, eg resname ALA GLY
+//     resid N...             Residue numbers, or ranges, eg resid 10-20 35
+//     chain ID...            eg chain A B
+//     index N...             0-based atom indices, or ranges, eg index 10-40
+//     backbone, sidechain    Protein backbone (N, CA, C, O) atoms, and the rest
+//     protein, water, hetero
+//     within DISTANCE of TERM
+//
+// Terms combine with `not`, `and`, and `or`, in that order of precedence, and
+// parentheses. `within`'s term binds like `not`'s, so `within 5 of resname LIG
+// and chain A` is `(within 5 of resname LIG) and chain A`. Keywords and names are
+// case-insensitive; chain IDs aren't.
+
+use std::collections::HashMap;
+
+use bonding;
+use molecule::Molecule;
+
+const BACKBONE: [&str; 5] = ["N", "CA", "C", "O", "OXT"];
+const AMINO_ACIDS: [&str; 22] = [
+    "ALA", "ARG", "ASN", "ASP", "CYS", "GLN", "GLU", "GLY", "HIS", "ILE", "LEU",
+    "LYS", "MET", "PHE", "PRO", "SER", "THR", "TRP", "TYR", "VAL", "SEC", "PYL",
+];
+const WATERS: [&str; 5] = ["HOH", "WAT", "H2O", "DOD", "SOL"];
+
+const KEYWORDS: [&str; 19] = [
+    "and", "or", "not", "within", "of", "all", "none", "element", "name", "resname",
+    "resid", "chain", "index", "backbone", "sidechain", "protein", "water", "hetero", "(",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    All,
+    None,
+    Element(Vec<String>),
+    Name(Vec<String>),
+    ResName(Vec<String>),
+    ResId(Vec<(i32, i32)>),  // Inclusive ranges.
+    Chain(Vec<char>),
+    Index(Vec<(usize, usize)>),  // Inclusive ranges.
+    Backbone,
+    Sidechain,
+    Protein,
+    Water,
+    Hetero,
+    Within(f32, Box<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.replace('(', " ( ").replace(')', " ) ")
+        .split_whitespace().map(|s| s.to_string()).collect()
+}
+
+fn is_keyword(token: &str) -> bool {
+    token == ")" || KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(token))
+}
+
+fn parse_range<T: ::std::str::FromStr + Copy + PartialOrd>(token: &str) -> Option<(T, T)> {
+    // A number, or an inclusive range, eg 10-40. A leading - is a sign, not a
+    // range, so negative residue numbers work. Reversed ranges are invalid.
+    match token.char_indices().skip(1).find(|(_, c)| *c == '-') {
+        Some((i, _)) => {
+            let (start, end) = (token[..i].parse().ok()?, token[i + 1..].parse().ok()?);
+            if start > end {
+                return None;
+            }
+            Some((start, end))
+        }
+        None => {
+            let n = token.parse().ok()?;
+            Some((n, n))
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_ref())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let result = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        result
+    }
+
+    fn accept(&mut self, keyword: &str) -> bool {
+        if self.peek().map_or(false, |t| t.eq_ignore_ascii_case(keyword)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn values(&mut self, keyword: &str) -> Result<Vec<String>, String> {
+        // Values following a keyword, up to the next keyword.
+        let mut result = Vec::new();
+        while let Some(token) = self.peek().map(|t| t.to_string()) {
+            if is_keyword(&token) {
+                break;
+            }
+            result.push(token);
+            self.pos += 1;
+        }
+        if result.is_empty() {
+            return Err(format!("Expected values after \"{}\"", keyword));
+        }
+        Ok(result)
+    }
+
+    fn ranges<T: ::std::str::FromStr + Copy + PartialOrd>(&mut self, keyword: &str) -> Result<Vec<(T, T)>, String> {
+        self.values(keyword)?.iter()
+            .map(|v| parse_range(v).ok_or_else(|| format!("Invalid {} range: \"{}\"", keyword, v)))
+            .collect()
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut result = self.and()?;
+        while self.accept("or") {
+            result = Expr::Or(Box::new(result), Box::new(self.and()?));
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut result = self.not()?;
+        while self.accept("and") {
+            result = Expr::And(Box::new(result), Box::new(self.not()?));
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.accept("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.term()
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("Unexpected end of expression")?;
+
+        Ok(match token.to_lowercase().as_ref() {
+            "(" => {
+                let result = self.or()?;
+                if self.next().as_ref().map(|t| t.as_ref()) != Some(")") {
+                    return Err("Expected \")\"".to_string());
+                }
+                result
+            }
+            "all" => Expr::All,
+            "none" => Expr::None,
+            "element" => Expr::Element(self.values("element")?),
+            "name" => Expr::Name(self.values("name")?),
+            "resname" => Expr::ResName(self.values("resname")?),
+            "resid" => Expr::ResId(self.ranges("resid")?),
+            "index" => Expr::Index(self.ranges("index")?),
+            "chain" => {
+                let ids = self.values("chain")?;
+                if let Some(id) = ids.iter().find(|id| id.chars().count() != 1) {
+                    return Err(format!("Chain IDs are single characters, not \"{}\"", id));
+                }
+                Expr::Chain(ids.iter().map(|id| id.chars().next().unwrap()).collect())
+            }
+            "backbone" => Expr::Backbone,
+            "sidechain" => Expr::Sidechain,
+            "protein" => Expr::Protein,
+            "water" => Expr::Water,
+            "hetero" => Expr::Hetero,
+            "within" => {
+                let distance = self.next().and_then(|d| d.parse::<f32>().ok())
+                    .filter(|d| d.is_finite() && *d >= 0.)
+                    .ok_or("Expected a distance of 0 or more after \"within\"")?;
+                if !self.accept("of") {
+                    return Err("Expected \"of\" after \"within\"'s distance".to_string());
+                }
+                Expr::Within(distance, Box::new(self.not()?))
+            }
+            _ => return Err(format!("Unexpected \"{}\"", token)),
+        })
+    }
+}
+
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: tokenize(text), pos: 0 };
+    let result = parser.or()?;
+    match parser.peek() {
+        Some(token) => Err(format!("Unexpected \"{}\"", token)),
+        None => Ok(result),
+    }
+}
+
+fn within(molecule: &Molecule, distance: f32, targets: &[bool]) -> Vec<bool> {
+    // Atoms within `distance` of any target atom, including the targets. Targets
+    // are binned into cells `distance` wide, as in `bonding`, so each atom is
+    // only compared against targets in its own and adjacent cells.
+    let cell_size = distance.max(0.1);
+    let mut grid: HashMap<(i32, i32, i32), Vec<usize>> = HashMap::new();
+    for (i, atom) in molecule.atoms.iter().enumerate().filter(|(i, _)| targets[*i]) {
+        grid.entry(bonding::cell(atom.position, cell_size)).or_insert_with(Vec::new).push(i);
+    }
+
+    molecule.atoms.iter().map(|atom| {
+        let (cx, cy, cz) = bonding::cell(atom.position, cell_size);
+        let p = atom.position;
+        (-1..=1).any(|dx| (-1..=1).any(|dy| (-1..=1).any(|dz| {
+            grid.get(&(cx + dx, cy + dy, cz + dz)).map_or(false, |cell| cell.iter().any(|&j| {
+                let q = molecule.atoms[j].position;
+                (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2) <= distance.powi(2)
+            }))
+        })))
+    }).collect()
+}
+
+fn mask<F: Fn(usize) -> bool>(n: usize, f: F) -> Vec<bool> {
+    (0..n).map(f).collect()
+}
+
+impl Expr {
+    pub fn evaluate(&self, molecule: &Molecule) -> Vec<bool> {
+        // Whether each atom matches.
+        let n = molecule.atoms.len();
+        let any = |values: &[String], value: &str| values.iter().any(|v| v.eq_ignore_ascii_case(value));
+        let residue = |i: usize| molecule.atoms[i].residue.as_ref();
+        let is_protein = |i: usize| residue(i).map_or(false, |r| AMINO_ACIDS.contains(&r.name.as_ref()));
+        let is_backbone = |i: usize| is_protein(i) && BACKBONE.contains(&molecule.atoms[i].label.as_ref());
+
+        match self {
+            Expr::All => vec![true; n],
+            Expr::None => vec![false; n],
+            Expr::Element(symbols) => mask(n, |i| any(symbols, &molecule.atoms[i].element)),
+            Expr::Name(names) => mask(n, |i| any(names, &molecule.atoms[i].label)),
+            Expr::ResName(names) => mask(n, |i| residue(i).map_or(false, |r| any(names, &r.name))),
+            Expr::ResId(ranges) => mask(n, |i| residue(i).map_or(false, |r| {
+                ranges.iter().any(|(lo, hi)| r.seq >= *lo && r.seq <= *hi)
+            })),
+            Expr::Chain(ids) => mask(n, |i| residue(i).map_or(false, |r| ids.contains(&r.chain))),
+            Expr::Index(ranges) => mask(n, |i| ranges.iter().any(|(lo, hi)| i >= *lo && i <= *hi)),
+            Expr::Backbone => mask(n, |i| is_backbone(i)),
+            Expr::Sidechain => mask(n, |i| is_protein(i) && !is_backbone(i)),
+            Expr::Protein => mask(n, |i| is_protein(i)),
+            Expr::Water => mask(n, |i| residue(i).map_or(false, |r| WATERS.contains(&r.name.as_ref()))),
+            Expr::Hetero => mask(n, |i| molecule.atoms[i].hetero),
+            Expr::Within(distance, expr) => within(molecule, *distance, &expr.evaluate(molecule)),
+            Expr::Not(expr) => expr.evaluate(molecule).iter().map(|m| !m).collect(),
+            Expr::And(a, b) => a.evaluate(molecule).iter().zip(b.evaluate(molecule))
+                .map(|(a, b)| *a && b).collect(),
+            Expr::Or(a, b) => a.evaluate(molecule).iter().zip(b.evaluate(molecule))
+                .map(|(a, b)| *a || b).collect(),
+        }
+    }
+}
+
+pub fn select(molecule: &Molecule, text: &str) -> Result<Vec<usize>, String> {
+    // Indices of the atoms matching an expression, in order.
+    let mask = parse(text)?.evaluate(molecule);
+    Ok((0..mask.len()).filter(|i| mask[*i]).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use molecule::{Atom, Residue};
+
+    fn atom(element: &str, name: &str, res: &str, seq: i32, chain: char, x: f32) -> Atom {
+        Atom {
+            label: name.to_string(),
+            residue: Some(Residue { name: res.to_string(), seq, chain }),
+            hetero: res == "LIG" || res == "HOH",
+            ..Atom::new(element, [x, 0., 0.])
+        }
+    }
+
+    #[test]
+    fn parse_exprs() {
+        assert_eq!(parse("element C N").unwrap(),
+                   Expr::Element(vec!["C".to_string(), "N".to_string()]));
+        assert_eq!(parse("not index 1-3 or all").unwrap(), Expr::Or(
+            Box::new(Expr::Not(Box::new(Expr::Index(vec![(1, 3)])))), Box::new(Expr::All)
+        ));
+        assert_eq!(parse("resid -2-4 7").unwrap(), Expr::ResId(vec![(-2, 4), (7, 7)]));
+        assert_eq!(parse("within 5 of (water)").unwrap(), Expr::Within(5., Box::new(Expr::Water)));
+
+        assert!(parse("").is_err());
+        assert!(parse("element").is_err());
+        assert!(parse("chain AB").is_err());
+        assert!(parse("(all").is_err());
+        assert!(parse("all all").is_err());
+        assert!(parse("within five of all").is_err());
+        assert!(parse("index a-b").is_err());
+        assert!(parse("index 40-10").is_err());
+        assert!(parse("within -5 of all").is_err());
+        assert!(parse("within NaN of all").is_err());
+        assert!(parse("within inf of all").is_err());
+    }
+
+    #[test]
+    fn evaluate() {
+        let molecule = Molecule::new("", vec![
+            atom("N", "N", "ALA", 1, 'A', 0.),
+            atom("C", "CA", "ALA", 1, 'A', 1.),
+            atom("C", "CB", "ALA", 1, 'A', 2.),
+            atom("C", "CA", "GLY", 2, 'B', 10.),
+            atom("C", "C1", "LIG", 100, 'B', 14.),
+            atom("O", "O", "HOH", 200, 'B', 30.),
+        ], Vec::new());
+        let select = |text: &str| select(&molecule, text).unwrap();
+
+        assert_eq!(select("element C"), vec![1, 2, 3, 4]);
+        assert_eq!(select("chain A and backbone"), vec![0, 1]);
+        assert_eq!(select("sidechain"), vec![2]);
+        assert_eq!(select("name ca or water"), vec![1, 3, 5]);
+        assert_eq!(select("index 2-3 5"), vec![2, 3, 5]);
+        assert_eq!(select("resid 2-100 and not hetero"), vec![3]);
+        assert_eq!(select("element C and within 5 of resname LIG"), vec![3, 4]);
+        assert_eq!(select("protein and not within 5 of (resname LIG or water)"), vec![0, 1, 2]);
+        assert_eq!(select("none or not all"), Vec::<usize>::new());
+    }
+}
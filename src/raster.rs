@@ -0,0 +1,463 @@
+// A CPU rasterizer that mirrors our Vulkan pipeline: the same vertex and index
+// data (from `Shape::vertex_data`), the same uniforms, and ports of vert.glsl and
+// frag.glsl. Used to render images when no Vulkan device is available, eg on
+// servers, and to test the whole pipeline without a GPU.
+//
+// Like the pipeline, it uses a depth buffer with a less-than test, clamps depth
+// rather than clipping to the near and far planes, and doesn't cull back faces.
+// Varyings are interpolated perspective-correctly.
+
+use std::collections::HashMap;
+
+use ops::{dot, dot_v, normalize, transpose};
+use renderer::{scene_uniforms, Renderer};
+use types::{Camera, Scene, ShaderVertex, Uniforms};
+
+// Triangles are clipped where w drops below this, ie just in front of the eye.
+const W_MIN: f32 = 1e-5;
+// Number of floats in `Varyings`, as interpolated.
+const NUM_VARYINGS: usize = 15;
+// Matches frag.glsl.
+const HIGHLIGHT_COLOR: [f32; 3] = [1., 0.85, 0.2];
+
+#[derive(Copy, Clone, Debug)]
+pub struct Varyings {
+    // Outputs of the vertex shader, and inputs of the fragment shader.
+    pub v_normal: [f32; 3],
+    pub face_color: [f32; 4],
+    pub diffuse_direction: [f32; 3],
+    pub ambient_color: [f32; 4],
+    pub highlight: f32,
+}
+
+impl Varyings {
+    fn to_vec(&self) -> [f32; NUM_VARYINGS] {
+        let mut result = [0.; NUM_VARYINGS];
+        result[0..3].copy_from_slice(&self.v_normal);
+        result[3..7].copy_from_slice(&self.face_color);
+        result[7..10].copy_from_slice(&self.diffuse_direction);
+        result[10..14].copy_from_slice(&self.ambient_color);
+        result[14] = self.highlight;
+        result
+    }
+
+    fn from_vec(v: &[f32; NUM_VARYINGS]) -> Self {
+        Self {
+            v_normal: [v[0], v[1], v[2]],
+            face_color: [v[3], v[4], v[5], v[6]],
+            diffuse_direction: [v[7], v[8], v[9]],
+            ambient_color: [v[10], v[11], v[12], v[13]],
+            highlight: v[14],
+        }
+    }
+}
+
+fn glsl(M: [[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    // The matrix GLSL sees when we upload `M`; it reads matrices column-major.
+    transpose(M)
+}
+
+fn inverse_transpose3(M: [[f32; 4]; 4]) -> [[f32; 3]; 3] {
+    // transpose(inverse(mat3(M))): the cofactor matrix, over the determinant.
+    let c = |r0: usize, r1: usize, c0: usize, c1: usize|
+        M[r0][c0] * M[r1][c1] - M[r0][c1] * M[r1][c0];
+
+    let cof = [
+        [c(1, 2, 1, 2), -c(1, 2, 0, 2), c(1, 2, 0, 1)],
+        [-c(0, 2, 1, 2), c(0, 2, 0, 2), -c(0, 2, 0, 1)],
+        [c(0, 1, 1, 2), -c(0, 1, 0, 2), c(0, 1, 0, 1)],
+    ];
+    let det = M[0][0] * cof[0][0] + M[0][1] * cof[0][1] + M[0][2] * cof[0][2];
+
+    let mut result = [[0.; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            result[i][j] = cof[i][j] / det;
+        }
+    }
+    result
+}
+
+pub fn vertex_shader(v: &ShaderVertex, u: &Uniforms) -> ([f32; 4], Varyings) {
+    // Port of vert.glsl. Returns the clip-space position (gl_Position), and outputs.
+    let model = dot(glsl(u.t_model), glsl(u.r_model));
+    let worldview = dot(glsl(u.view), model);
+
+    let N = inverse_transpose3(glsl(u.r_model));
+    let n = [-v.normal[0], -v.normal[1], -v.normal[2]];
+    let v_normal = [
+        N[0][0] * n[0] + N[0][1] * n[1] + N[0][2] * n[2],
+        N[1][0] * n[0] + N[1][1] * n[1] + N[1][2] * n[2],
+        N[2][0] * n[0] + N[2][1] * n[1] + N[2][2] * n[2],
+    ];
+
+    let p = v.position;
+    let position = dot_v(&dot(glsl(u.proj), worldview), [p[0], p[1], p[2], 1.]);
+
+    (position, Varyings {
+        v_normal,
+        face_color: v.face_color,
+        diffuse_direction: u.diffuse_direction,
+        ambient_color: u.ambient_color,
+        highlight: u.highlight,
+    })
+}
+
+pub fn fragment_shader(v: &Varyings) -> [f32; 4] {
+    // Port of frag.glsl.
+    let (n, d) = (normalize(&v.v_normal), normalize(&v.diffuse_direction));
+    let brightness = n[0] * d[0] + n[1] * d[1] + n[2] * d[2];
+
+    let mut result = [0., 0., 0., 1.];
+    for i in 0..3 {
+        // GLSL's mix.
+        result[i] = v.ambient_color[i] + (v.face_color[i] - v.ambient_color[i]) * brightness;
+        result[i] += (HIGHLIGHT_COLOR[i] - result[i]) * v.highlight * 0.5;
+    }
+    result
+}
+
+fn clip_near(poly: Vec<([f32; 4], [f32; NUM_VARYINGS])>) -> Vec<([f32; 4], [f32; NUM_VARYINGS])> {
+    // Clip a polygon in clip space against the plane w = W_MIN, interpolating
+    // positions and varyings at the crossing points.
+    let mut result = Vec::new();
+
+    for i in 0..poly.len() {
+        let (a, b) = (poly[i], poly[(i + 1) % poly.len()]);
+        let (a_in, b_in) = (a.0[3] >= W_MIN, b.0[3] >= W_MIN);
+
+        if a_in {
+            result.push(a);
+        }
+        if a_in != b_in {
+            let t = (W_MIN - a.0[3]) / (b.0[3] - a.0[3]);
+            let mut pos = [0.; 4];
+            let mut vary = [0.; NUM_VARYINGS];
+            for j in 0..4 { pos[j] = a.0[j] + (b.0[j] - a.0[j]) * t }
+            for j in 0..NUM_VARYINGS { vary[j] = a.1[j] + (b.1[j] - a.1[j]) * t }
+            result.push((pos, vary));
+        }
+    }
+    result
+}
+
+pub struct Target {
+    // Color and depth attachments. Rows run top to bottom.
+    pub width: u32,
+    pub height: u32,
+    pub color: Vec<[f32; 4]>,
+    pub depth: Vec<f32>,
+}
+
+impl Target {
+    pub fn new(width: u32, height: u32, background: [f32; 4]) -> Self {
+        let len = (width * height) as usize;
+        Self { width, height, color: vec![background; len], depth: vec![1.; len] }
+    }
+
+    pub fn draw_indexed(&mut self, indices: &[u32], vertices: &[ShaderVertex], u: &Uniforms) {
+        // Draw a triangle list, like the pipeline's `draw_indexed`.
+        let shaded: Vec<([f32; 4], [f32; NUM_VARYINGS])> = vertices.iter().map(|v| {
+            let (position, varyings) = vertex_shader(v, u);
+            (position, varyings.to_vec())
+        }).collect();
+
+        for tri in indices.chunks(3) {
+            if tri.len() < 3 {
+                break;
+            }
+            let poly = clip_near(tri.iter().map(|i| shaded[*i as usize]).collect());
+
+            // Clipping can leave a quad; fan it back into triangles.
+            for i in 1..poly.len().saturating_sub(1) {
+                self.rasterize([poly[0], poly[i], poly[i + 1]]);
+            }
+        }
+    }
+
+    fn rasterize(&mut self, tri: [([f32; 4], [f32; NUM_VARYINGS]); 3]) {
+        let (w, h) = (self.width as f32, self.height as f32);
+
+        // Viewport transform: x and y in pixels, z in [0, 1]. 1/w is kept for
+        // perspective-correct interpolation.
+        let mut pts = [[0_f32; 4]; 3];
+        for j in 0..3 {
+            let clip = tri[j].0;
+            pts[j] = [
+                (clip[0] / clip[3] + 1.) / 2. * w,
+                (clip[1] / clip[3] + 1.) / 2. * h,
+                clip[2] / clip[3],
+                1. / clip[3],
+            ];
+        }
+
+        let area = (pts[1][0] - pts[0][0]) * (pts[2][1] - pts[0][1]) -
+            (pts[2][0] - pts[0][0]) * (pts[1][1] - pts[0][1]);
+        if area.abs() < 1e-12 {
+            return;
+        }
+
+        let min_x = pts.iter().map(|p| p[0]).fold(w, f32::min).max(0.).floor() as u32;
+        let max_x = pts.iter().map(|p| p[0]).fold(0., f32::max).min(w - 1.).ceil() as u32;
+        let min_y = pts.iter().map(|p| p[1]).fold(h, f32::min).max(0.).floor() as u32;
+        let max_y = pts.iter().map(|p| p[1]).fold(0., f32::max).min(h - 1.).ceil() as u32;
+
+        for y in min_y..=max_y.min(self.height - 1) {
+            for x in min_x..=max_x.min(self.width - 1) {
+                // Sample at pixel centers.
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let edge = |a: [f32; 4], b: [f32; 4]|
+                    ((b[0] - a[0]) * (py - a[1]) - (b[1] - a[1]) * (px - a[0])) / area;
+                let b = [edge(pts[1], pts[2]), edge(pts[2], pts[0]), edge(pts[0], pts[1])];
+                if b[0] < 0. || b[1] < 0. || b[2] < 0. {
+                    continue;
+                }
+
+                // Depth is linear in screen space. Depth clamping is enabled.
+                let z = (b[0] * pts[0][2] + b[1] * pts[1][2] + b[2] * pts[2][2]).max(0.).min(1.);
+                let i = (y * self.width + x) as usize;
+                if z >= self.depth[i] {
+                    continue;
+                }
+
+                // Varyings aren't; interpolate them over w, then divide by
+                // the interpolated 1/w.
+                let weights = [b[0] * pts[0][3], b[1] * pts[1][3], b[2] * pts[2][3]];
+                let total = weights[0] + weights[1] + weights[2];
+                let mut vary = [0.; NUM_VARYINGS];
+                for k in 0..NUM_VARYINGS {
+                    vary[k] = (weights[0] * tri[0].1[k] + weights[1] * tri[1].1[k] +
+                        weights[2] * tri[2].1[k]) / total;
+                }
+
+                self.depth[i] = z;
+                self.color[i] = fragment_shader(&Varyings::from_vec(&vary));
+            }
+        }
+    }
+
+    pub fn pixels(&self) -> Vec<u8> {
+        // RGBA, 8 bits per channel, as stored in a Unorm image.
+        let mut result = Vec::with_capacity(self.color.len() * 4);
+        for c in &self.color {
+            for v in c {
+                result.push((v.max(0.).min(1.) * 255.).round() as u8);
+            }
+        }
+        result
+    }
+}
+
+pub struct SoftwareRenderer {
+    target: Target,
+    background: [f32; 4],
+    buffers: HashMap<u32, (Vec<u32>, Vec<ShaderVertex>)>,
+    uniforms: HashMap<u32, Uniforms>,
+}
+
+impl SoftwareRenderer {
+    pub fn new(dimensions: [u32; 2]) -> Self {
+        let background = [0., 0., 0., 1.];
+        Self {
+            target: Target::new(dimensions[0], dimensions[1], background),
+            background,
+            buffers: HashMap::new(),
+            uniforms: HashMap::new(),
+        }
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn upload_scene(&mut self, scene: &Scene) {
+        self.buffers = scene.shapes.iter().map(|(id, shape)| (*id, shape.vertex_data())).collect();
+        self.update_uniforms(scene);
+    }
+
+    fn update_uniforms(&mut self, scene: &Scene) {
+        self.uniforms = scene_uniforms(scene, &scene.cam);
+    }
+
+    fn set_background(&mut self, color: [f32; 4]) {
+        self.background = color;
+    }
+
+    fn draw_frame(&mut self) -> Result<(), String> {
+        self.target = Target::new(self.target.width, self.target.height, self.background);
+
+        for (id, u) in &self.uniforms {
+            // Shapes added since the last upload aren't drawn.
+            if let Some((indices, vertices)) = self.buffers.get(id) {
+                self.target.draw_indexed(indices, vertices, u);
+            }
+        }
+        Ok(())
+    }
+
+    fn resize(&mut self, dimensions: [u32; 2]) {
+        self.target = Target::new(dimensions[0], dimensions[1], self.background);
+    }
+
+    fn dimensions(&self) -> [u32; 2] {
+        [self.target.width, self.target.height]
+    }
+
+    fn read_pixels(&mut self) -> Result<Vec<u8>, String> {
+        self.draw_frame()?;
+        Ok(self.target.pixels())
+    }
+}
+
+pub fn render(scene: &Scene, cam: &Camera, width: u32, height: u32,
+              background: [f32; 4]) -> Vec<u8> {
+    // Render a single frame without setting up a `SoftwareRenderer`. Returns RGBA
+    // pixels, 8 bits per channel, starting with the top row.
+    let mut target = Target::new(width, height, background);
+
+    for (id, u) in &scene_uniforms(scene, cam) {
+        let (indices, vertices) = scene.shapes[id].vertex_data();
+        target.draw_indexed(&indices, &vertices, u);
+    }
+
+    target.pixels()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shape_maker;
+    use transforms;
+    use scenes;
+    use types::{Normal, Shape, Vertex};
+
+    fn ascii(pixels: &[u8], width: u32) -> String {
+        // A coarse picture of an image for golden tests: ' ' for black, otherwise
+        // a digit for brightness.
+        let mut result = String::new();
+        for (i, px) in pixels.chunks(4).enumerate() {
+            let sum = px[0] as u32 + px[1] as u32 + px[2] as u32;
+            result.push(if sum == 0 {
+                ' '
+            } else {
+                ::std::char::from_digit((sum * 10 / (3 * 256)).min(9), 10).unwrap()
+            });
+            if (i as u32 + 1) % width == 0 {
+                result.push('\n');
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn golden_scene_1() {
+        let scene = scenes::scene_1(2.);
+        let mut cam = scene.cam.clone();
+        cam.aspect = 2.;
+
+        let (w, h) = (40, 20);
+        let pixels = render(&scene, &cam, w, h, [0., 0., 0., 1.]);
+        // Three nuclei; the nearest is the largest. The one at the origin is too
+        // small to cover a pixel center at this size.
+        let expected = [
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "               2                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                      11                ",
+            "                      221               ",
+            "                       2                ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+            "                                        ",
+        ];
+        let expected: String = expected.iter().map(|r| format!("{}\n", r)).collect();
+        assert_eq!(ascii(&pixels, w), expected);
+    }
+
+    #[test]
+    fn renderer_matches_render() {
+        let mut scene = scenes::scene_1(2.);
+        let mut renderer = SoftwareRenderer::new([40, 20]);
+        renderer.set_background([0., 0., 0.2, 1.]);
+        renderer.upload_scene(&scene);
+        let pixels = renderer.read_pixels().unwrap();
+        assert_eq!(pixels, render(&scene, &scene.cam, 40, 20, [0., 0., 0.2, 1.]));
+
+        // Moving a shape only needs new uniforms.
+        scene.shapes.get_mut(&0).unwrap().position = [1., 1., 0.];
+        renderer.update_uniforms(&scene);
+        assert_ne!(renderer.read_pixels().unwrap(), pixels);
+
+        renderer.resize([8, 6]);
+        assert_eq!(renderer.read_pixels().unwrap().len(), 8 * 6 * 4);
+    }
+
+    #[test]
+    fn perspective_correct() {
+        // A quad receding from the camera, colored black at its near edge and
+        // white at its far edge. Perspective-correct interpolation puts the halfway
+        // color closer to the far edge on screen than the halfway point.
+        let mut scene = scenes::scene_1(1.);
+        scene.lighting.ambient_color = [0., 0., 0., 1.];
+        // Light straight on, so brightness is 1.
+        scene.lighting.diffuse_direction = [0., -1., 0.];
+
+        let shape = Shape::new(shape_maker::cube(1.), [0., 0., 0.], [0., 0., 0.]);
+        let u = Uniforms::new(&shape, transforms::view(&scene.cam.position, &scene.cam.orientation),
+                              transforms::proj(&scene.cam), &scene.lighting);
+
+        let near = -6.;
+        let far = 20.;
+        let corner = |x: f32, z: f32, c: f32| ShaderVertex::new(
+            Vertex::new(x, -1., z), Normal::new(0., -1., 0.), [c, c, c, 1.], 1.
+        );
+        let vertices = [corner(-3., near, 0.), corner(3., near, 0.),
+                        corner(3., far, 1.), corner(-3., far, 1.)];
+
+        let (w, h) = (1, 200);
+        let mut target = Target::new(w, h, [0., 0., 1., 1.]);
+        target.draw_indexed(&[0, 1, 2, 0, 2, 3], &vertices, &u);
+
+        let rows: Vec<(u32, f32)> = (0..h).filter_map(|y| {
+            let c = target.color[y as usize];
+            if c == [0., 0., 1., 1.] { None } else { Some((y, c[0])) }
+        }).collect();
+        assert!(rows.len() > 10);
+
+        // The quad's screen extent, and where its color crosses one half.
+        let (first, last) = (rows[0].0 as f32, rows[rows.len() - 1].0 as f32);
+        let halfway = rows.iter().min_by(|a, b| (a.1 - 0.5).abs().partial_cmp(&(b.1 - 0.5).abs()).unwrap())
+            .unwrap().0 as f32;
+
+        // Colors go from the far edge (1) to the near edge (0).
+        let far_row = if rows[0].1 > 0.5 { first } else { last };
+        assert!((halfway - far_row).abs() < (last - first) / 2. - 5.);
+    }
+
+    #[test]
+    fn clips_behind_camera() {
+        // A triangle passing through the camera plane is clipped, not dropped.
+        let scene = scenes::scene_1(1.);
+        let mut shapes = HashMap::new();
+        let mut vertices = HashMap::new();
+        vertices.insert(0, Vertex::new(-1., -1., -10.));
+        vertices.insert(1, Vertex::new(1., -1., -10.));
+        vertices.insert(2, Vertex::new(0., -1., 10.));
+        let mesh = ::types::Mesh::new(vertices, vec![vec![0, 1, 2]], vec![[1., 1., 1., 1.]],
+                                      vec![Normal::new(0., -1., 0.)]);
+        shapes.insert(0, Shape::new(mesh, [0., 0., 0.], [0., 0., 0.]));
+        let scene = Scene { shapes, ..scene };
+
+        let pixels = render(&scene, &scene.cam, 20, 20, [0., 0., 0., 1.]);
+        assert!(pixels.chunks(4).any(|p| p != [0, 0, 0, 255]));
+    }
+}
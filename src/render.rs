@@ -8,6 +8,7 @@
 // according to those terms.
 
 use std::collections::HashMap;
+use std::mem;
 use std::sync::Arc;
 use std::time;
 
@@ -32,15 +33,17 @@ use vulkano_win;
 use vulkano_win::VkSurfaceBuild;
 use winit;
 
+use bindings::Bindings;
 use input;
-use ops;
-use scenes;
-use transforms;
-use types::{Shape, ShaderVertex};
+use pick;
+use selection;
+use renderer::{scene_uniforms, Renderer};
+use types::{Scene, Shape, ShaderVertex, Uniforms};
 
 
 const WIDTH: u32 = 1024;
 const HEIGHT: u32 = 768;
+const TITLE: &str = "Chem graphics";
 
 mod vs {
     #[derive(VulkanoShader)]
@@ -65,35 +68,7 @@ pub fn make_static_buffers(shapes: &HashMap<u32, Shape>, device: Arc<device::Dev
     let mut vertex_buffers = HashMap::new();
 
     for (s_id, shape) in shapes {
-        let mut indices = Vec::new();
-        let mut vertex_info = Vec::new();
-        let mut index_modifier = 0;
-
-        let mut tri_indices: Vec<u32> = shape.mesh.tris.iter().map(|ind| ind + index_modifier).collect();
-        indices.append(&mut tri_indices);
-        index_modifier += shape.mesh.num_face_verts();
-
-        // todo could do separate normals buffer.
-        // Iterate over faces; each vertice is used once per face.
-        for (i, face) in shape.mesh.faces_vert.iter().enumerate() {
-            for vert_id in face {
-//                vertex_info.push(
-//                    ShaderVertex::new(
-//                        shape.mesh.vertices[vert_id],
-//                        shape.mesh.normals[i],
-//                        shape.mesh.face_colors[i],
-//                        shape.specular_intensity,
-//                    )
-                                vertex_info.push(
-                    ShaderVertex::new(
-                        shape.mesh.vertices[vert_id],
-                        shape.mesh.normals[i],
-                        shape.mesh.face_colors[i],
-                        shape.specular_intensity,
-                    )
-                );
-            }
-        }
+        let (indices, vertex_info) = shape.vertex_data();
 
         let index_buffer = CpuAccessibleBuffer::from_iter(device.clone(), buffer::BufferUsage::all(),
                                                           indices.iter().cloned())
@@ -110,176 +85,30 @@ pub fn make_static_buffers(shapes: &HashMap<u32, Shape>, device: Arc<device::Dev
     (index_buffers, vertex_buffers)
 }
 
-pub fn render() {
-    // todo for now, we'll keep state in this func.
-    // todo sync aspect with window dims.
-
-    let aspect = WIDTH as f32 / HEIGHT as f32;
-
-    let mut scene = scenes::scene_1(aspect);
-
-    let mut currently_pressed: Vec<u32> = Vec::new();
-
-    // The first step of any vulkan program is to create an instance.
-    let instance_ = {
-        // When we create an instance, we have to pass a list of extensions that we want to enable.
-        //
-        // All the window-drawing functionalities are part of non-core extensions that we need
-        // to enable manually. To do so, we ask the `vulkano_win` crate for the list of extensions
-        // required to draw to a window.
-        let extensions = vulkano_win::required_extensions();
-
-        // Now creating the instance.
-        instance::Instance::new(None, &extensions, None)
-            .expect("failed to create Vulkan instance")
-    };
-
-    // We then choose which physical device to use.
-    //
-    // In a real application, there are three things to take into consideration:
-    //
-    // - Some devices may not support some of the optional features that may be required by your
-    //   application. You should filter out the devices that don't support your app.
-    //
-    // - Not all devices can draw to a certain surface. Once you create your window, you have to
-    //   choose a device that is capable of drawing to it.
-    //
-    // - You probably want to leave the choice between the remaining devices to the user.
-    //
-    // For the sake of the example we are just going to use the first device, which should work
-    // most of the time.
-    let physical = instance::PhysicalDevice::enumerate(&instance_)
-        .next().expect("no device available");
-    // Some little debug infos.
-    println!("Using device: {} (type: {:?})", physical.name(), physical.ty());
-
-    // The objective of this example is to draw a triangle on a window. To do so, we first need to
-    // create the window.
-    //
-    // This is done by creating a `WindowBuilder` from the `winit` crate, then calling the
-    // `build_vk_surface` method provided by the `VkSurfaceBuild` trait from `vulkano_win`. If you
-    // ever get an error about `build_vk_surface` being undefined in one of your projects, this
-    // probably means that you forgot to import this trait.
-    //
-    // This returns a `vulkano::swapchain::Surface` object that contains both a cross-platform winit
-    // window and a cross-platform Vulkan surface that represents the surface of the window.
-    let mut events_loop = winit::EventsLoop::new();
-    let surface = winit::WindowBuilder::new().with_title("Chem graphics")
-        .build_vk_surface(&events_loop, instance_.clone()).unwrap();
-
-    // The next step is to choose which GPU queue will execute our draw commands.
-    //
-    // Devices can provide multiple queues to run commands in parallel (for example a draw queue
-    // and a compute queue), similar to CPU threads. This is something you have to have to manage
-    // manually in Vulkan.
-    //
-    // In a real-life application, we would probably use at least a graphics queue and a transfers
-    // queue to handle data transfers in parallel. In this example we only use one queue.
-    //
-    // We have to choose which queues to use early on, because we will need this info very soon.
-    let queue_family = physical.queue_families().find(|&q| {
-        // We take the first queue that supports drawing to our window.
-        q.supports_graphics() && surface.is_supported(q).unwrap_or(false)
-    }).expect("couldn't find a graphical queue family");
-
-    // Now initializing the device. This is probably the most important object of Vulkan.
-    //
-    // We have to pass five parameters when creating a device:
-    //
-    // - Which physical device to connect to.
-    //
-    // - A list of optional features and extensions that our program needs to work correctly.
-    //   Some parts of the Vulkan specs are optional and must be enabled manually at device
-    //   creation. In this example the only thing we are going to need is the `khr_swapchain`
-    //   extension that allows us to draw to a window.
-    //
-    // - A list of layers to enable. This is very niche, and you will usually pass `None`.
-    //
-    // - The list of queues that we are going to use. The exact parameter is an iterator whose
-    //   items are `(Queue, f32)` where the floating-point represents the priority of the queue
-    //   between 0.0 and 1.0. The priority of the queue is a hint to the implementation about how
-    //   much it should prioritize queues between one another.
-    //
-    // The list of created queues is returned by the function alongside with the device.
-    let (device_, mut queues) = {
-        let device_ext = device::DeviceExtensions {
-            khr_swapchain: true,
-            .. device::DeviceExtensions::none()
-        };
-
-        device::Device::new(physical, physical.supported_features(), &device_ext,
-                            [(queue_family, 0.5)].iter().cloned()).expect("failed to create device")
-    };
-
-    // Since we can request multiple queues, the `queues` variable is in fact an iterator. In this
-    // example we use only one queue, so we just retreive the first and only element of the
-    // iterator and throw it away.
-    let queue = queues.next().unwrap();
-
-    // The dimensions of the surface.
-    // This variable needs to be mutable since the viewport can change size.
-    let mut dimensions;
-
-    // Before we can draw on the surface, we have to create what is called a swapchain. Creating
-    // a swapchain allocates the color buffers that will contain the image that will ultimately
-    // be visible on the screen. These images are returned alongside with the swapchain.
-    let (mut swapchain_, mut images) = {
-        // Querying the capabilities of the surface. When we create the swapchain we can only
-        // pass values that are allowed by the capabilities.
-        let caps = surface.capabilities(physical)
-            .expect("failed to get surface capabilities");
-
-        dimensions = caps.current_extent.unwrap_or([WIDTH, HEIGHT]);
-
-        // We choose the dimensions of the swapchain to match the current extent of the surface.
-        // If `caps.current_extent` is `None`, this means that the window size will be determined
-        // by the dimensions of the swapchain, in which case we just use the width and height defined above.
-
-        let usage = caps.supported_usage_flags;
-        // The alpha mode indicates how the alpha value of the final image will behave. For example
-        // you can choose whether the window will be opaque or transparent.
-        let alpha = caps.supported_composite_alpha.iter().next().unwrap();
-        // Choosing the internal format that the images will have.
-        let format = caps.supported_formats[0].0;
-
-        // Please take a look at the docs for the meaning of the parameters we didn't mention.
-        swapchain::Swapchain::new(device_.clone(), surface.clone(), caps.min_image_count, format,
-                                  dimensions, 1, usage, &queue,
-                                  swapchain::SurfaceTransform::Identity, alpha,
-                                  swapchain::PresentMode::Fifo, true,
-                                  None).expect("failed to create swapchain")
-    };
-
-    let mut depth_buffer = image::attachment::AttachmentImage::transient(
-        device_.clone(), dimensions, format::D16Unorm).unwrap();
-
-    // todo sep normals buffer like in teapot example?
-    let (index_buffers, vertex_buffers) = make_static_buffers(&scene.shapes, device_.clone());
-
-    // todo move depth_buffer and unifform buffer to one of the make_buffer funcs?
-
-    let uniform_buffer = buffer::cpu_pool::CpuBufferPool::<vs::ty::Data>
-        ::new(device_.clone(), buffer::BufferUsage::all());
-
-    // The next step is to create the shaders.
-    //
-    // The raw shader creation API provided by the vulkano library is unsafe, for various reasons.
-    //
-    // An overview of what the `VulkanoShader` derive macro generates can be found in the
-    // `vulkano-shader-derive` crate docs. You can view them at
-    // https://docs.rs/vulkano-shader-derive/*/vulkano_shader_derive/
-    let vs = vs::Shader::load(device_.clone()).expect("failed to create shader module");
-    let fs = fs::Shader::load(device_.clone()).expect("failed to create shader module");
-
-    // At this point, OpenGL initialization would be finished. However in Vulkan it is not. OpenGL
-    // implicitely does a lot of computation whenever you draw. In Vulkan, you have to do all this
-    // manually.
+fn shader_data(u: &Uniforms) -> vs::ty::Data {
+    vs::ty::Data {
+        model: u.model,
+        view: u.view,
+        proj: u.proj,
+        r_model: u.r_model,
+        t_model: u.t_model,
+        ambient_color: u.ambient_color,
+        diffuse_color: u.diffuse_color,
+        diffuse_direction: u.diffuse_direction,
+        ambient_intensity: u.ambient_intensity,
+        diffuse_intensity: u.diffuse_intensity,
+        shape_opacity: u.shape_opacity,
+        highlight: u.highlight,
+    }
+}
 
-    // The next step is to create a *render pass*, which is an object that describes where the
-    // output of the graphics pipeline will go. It describes the layout of the images
-    // where the colors, depth and/or stencil information will be written.
-    let render_pass = Arc::new(
-        single_pass_renderpass!(device_.clone(),
+fn make_render_pass(device_: Arc<device::Device>, color_format: format::Format)
+        -> Arc<framebuffer::RenderPassAbstract + Send + Sync> {
+    // A *render pass* is an object that describes where the output of the graphics
+    // pipeline will go. It describes the layout of the images where the colors,
+    // depth and/or stencil information will be written.
+    Arc::new(
+        single_pass_renderpass!(device_,
             attachments: {
                 // `color` is a custom name we give to the first and only attachment.
                 color: {
@@ -294,7 +123,7 @@ pub fn render() {
                     // of your structs that implements the `FormatDesc` trait). Here we use the
                     // generic `vulkano::format::Format` enum because we don't know the format in
                     // advance.
-                    format: swapchain_.format(),
+                    format: color_format,
                     samples: 1,
                 },
                 depth: {
@@ -311,14 +140,25 @@ pub fn render() {
                 depth_stencil: {depth}
             }
         ).unwrap()
-    );
+    )
+}
 
-    // Before we draw we have to create what is called a pipeline. This is similar to an OpenGL
-    // program, but much more specific.
+fn make_pipeline(device_: Arc<device::Device>,
+                 render_pass: Arc<framebuffer::RenderPassAbstract + Send + Sync>)
+        -> Arc<pipeline::GraphicsPipelineAbstract + Send + Sync> {
+    // The raw shader creation API provided by the vulkano library is unsafe, for various reasons.
+    //
+    // An overview of what the `VulkanoShader` derive macro generates can be found in the
+    // `vulkano-shader-derive` crate docs. You can view them at
+    // https://docs.rs/vulkano-shader-derive/*/vulkano_shader_derive/
+    let vs = vs::Shader::load(device_.clone()).expect("failed to create shader module");
+    let fs = fs::Shader::load(device_.clone()).expect("failed to create shader module");
+
+    // A pipeline is similar to an OpenGL program, but much more specific.
     // Info on what we can configure here:
     // https://docs.rs/vulkano/0.10.0/vulkano/pipeline/struct.GraphicsPipelineBuilder.html
     // Leaving default options explicit here to make it easier to configure.
-    let pipeline_ = Arc::new(pipeline::GraphicsPipeline::start()
+    Arc::new(pipeline::GraphicsPipeline::start()
         .vertex_input_single_buffer() // todo
         // A Vulkan shader can in theory contain multiple entry points, so we have to specify
         // which one. The `main` word of `main_entry_point` actually corresponds to the name of
@@ -336,273 +176,538 @@ pub fn render() {
 
         // We have to indicate which subpass of which render pass this pipeline is going to be used
         // in. The pipeline will only be usable from this particular subpass.
-        .render_pass(framebuffer::Subpass::from(render_pass.clone(), 0).unwrap())
+        .render_pass(framebuffer::Subpass::from(render_pass, 0).unwrap())
         // Now that our builder is filled, we call `build()` to obtain an actual pipeline.
-        .build(device_.clone())
-        .unwrap());
+        .build(device_)
+        .unwrap())
+}
 
-    // The render pass we created above only describes the layout of our framebuffers. Before we
-    // can draw we also need to create the actual framebuffers.
-    //
-    // Since we need to draw to multiple images, we are going to create a different framebuffer for
-    // each image.
-    let mut framebuffers: Option<Vec<Arc<framebuffer::Framebuffer<_,_>>>> = None;
+fn dynamic_state(dimensions: [u32; 2]) -> command_buffer::DynamicState {
+    // Use a viewport covering the whole target.
+    command_buffer::DynamicState {
+        line_width: None,
+        viewports: Some(vec![pipeline::viewport::Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+            depth_range: 0.0 .. 1.0,
+        }]),
+        scissors: None,
+    }
+}
 
-    // Initialization is finally finished!
+struct ShapeBuffers {
+    // GPU-side data for a scene's shapes, keyed by shape id.
+    index_buffers: HashMap<u32, Arc<CpuAccessibleBuffer<[u32]>>>,
+    vertex_buffers: HashMap<u32, Arc<CpuAccessibleBuffer<[ShaderVertex]>>>,
+    uniforms: HashMap<u32, Uniforms>,
+    uniform_pool: buffer::cpu_pool::CpuBufferPool<vs::ty::Data>,
+}
 
+impl ShapeBuffers {
+    fn draw(&self, mut command_buffer_: command_buffer::AutoCommandBufferBuilder,
+            pipeline_: &Arc<pipeline::GraphicsPipelineAbstract + Send + Sync>,
+            dimensions: [u32; 2]) -> Result<command_buffer::AutoCommandBufferBuilder, String> {
+        // Add draw commands for each shape to a command buffer that's inside a render pass.
+        let dynamic_state = dynamic_state(dimensions);
+
+        for (shape_id, uniforms) in &self.uniforms {
+            // Shapes added since the last upload have no buffers yet.
+            let (vertex_buffer, index_buffer) = match (self.vertex_buffers.get(shape_id),
+                                                       self.index_buffers.get(shape_id)) {
+                (Some(v), Some(i)) => (v.clone(), i.clone()),
+                _ => continue,
+            };
+
+            let uniform_buffer = self.uniform_pool.next(shader_data(uniforms))
+                .map_err(|e| format!("failed to allocate uniforms: {:?}", e))?;
+            let set = Arc::new(descriptor::descriptor_set::PersistentDescriptorSet::start(pipeline_.clone(), 0)
+                .add_buffer(uniform_buffer)
+                .map_err(|e| format!("failed to bind uniforms: {:?}", e))?
+                .build()
+                .map_err(|e| format!("failed to bind uniforms: {:?}", e))?
+            );
+
+            // The last two parameters contain the list of resources to pass to the shaders.
+            command_buffer_ = command_buffer_.draw_indexed(
+                pipeline_.clone(), &dynamic_state, vertex_buffer, index_buffer, set, ()
+            ).map_err(|e| format!("failed to record draw: {:?}", e))?;
+        }
+        Ok(command_buffer_)
+    }
+}
+
+struct WindowTarget {
+    surface: Arc<swapchain::Surface<winit::Window>>,
+    swapchain_: Arc<swapchain::Swapchain<winit::Window>>,
+    images: Vec<Arc<image::SwapchainImage<winit::Window>>>,
+    render_pass: Arc<framebuffer::RenderPassAbstract + Send + Sync>,
+    pipeline_: Arc<pipeline::GraphicsPipelineAbstract + Send + Sync>,
+    // Because framebuffers contain an Arc on the old swapchain, we need to
+    // recreate them along with it.
+    framebuffers: Option<Vec<Arc<framebuffer::FramebufferAbstract + Send + Sync>>>,
     // In some situations, the swapchain will become invalid by itself. This includes for example
     // when the window is resized (as the images of the swapchain will no longer match the
     // window's) or, on Android, when the application went to the background and goes back to the
-    // foreground.
-    //
-    // In this situation, acquiring a swapchain image or presenting it will return an error.
-    // Rendering to an image of that swapchain will not produce any error, but may or may not work.
-    // To continue rendering, we need to recreate the swapchain by creating a new swapchain.
-    // Here, we remember that we need to do this for the next loop iteration.
-    let mut recreate_swapchain = false;
-
-    // In the loop below we are going to submit commands to the GPU. Submitting a command produces
-    // an object that implements the `GpuFuture` trait, which holds the resources for as long as
-    // they are in use by the GPU.
+    // foreground. Here, we remember that we need to recreate it before the next frame.
+    recreate_swapchain: bool,
+}
+
+struct OffscreenTarget {
+    // An image in GPU memory we render to without a window, and read back.
+    dimensions: [u32; 2],
+    // Unorm rather than sRGB, so the output matches the software renderer's.
+    color_image: Arc<image::AttachmentImage<format::Format>>,
+    framebuffer_: Arc<framebuffer::FramebufferAbstract + Send + Sync>,
+    pipeline_: Arc<pipeline::GraphicsPipelineAbstract + Send + Sync>,
+}
+
+impl OffscreenTarget {
+    fn new(device_: Arc<device::Device>, dimensions: [u32; 2],
+           depth_buffer: Arc<image::AttachmentImage<format::D16Unorm>>) -> Result<Self, String> {
+        let color_format = format::Format::R8G8B8A8Unorm;
+
+        let color_image = image::attachment::AttachmentImage::with_usage(
+            device_.clone(), dimensions, color_format,
+            image::ImageUsage { color_attachment: true, transfer_source: true, .. image::ImageUsage::none() }
+        ).map_err(|e| format!("failed to create image: {:?}", e))?;
+
+        let render_pass = make_render_pass(device_.clone(), color_format);
+        let pipeline_ = make_pipeline(device_.clone(), render_pass.clone());
+
+        let framebuffer_ = Arc::new(framebuffer::Framebuffer::start(render_pass.clone())
+            .add(color_image.clone())
+            .and_then(|f| f.add(depth_buffer))
+            .map_err(|e| format!("failed to create framebuffer: {:?}", e))?
+            .build()
+            .map_err(|e| format!("failed to create framebuffer: {:?}", e))?);
+
+        Ok(Self { dimensions, color_image, framebuffer_, pipeline_ })
+    }
+}
+
+pub struct VulkanRenderer {
+    device_: Arc<device::Device>,
+    queue: Arc<device::Queue>,
+    dimensions: [u32; 2],
+    background: [f32; 4],
+    depth_buffer: Arc<image::AttachmentImage<format::D16Unorm>>,
+    buffers: ShapeBuffers,
+    // Only windowed renderers have a window target; offscreen targets are made
+    // when we first read pixels.
+    window: Option<WindowTarget>,
+    offscreen: Option<OffscreenTarget>,
+    // Submitting a command produces an object that implements the `GpuFuture` trait, which
+    // holds the resources for as long as they are in use by the GPU.
     //
     // Destroying the `GpuFuture` blocks until the GPU is finished executing it. In order to avoid
     // that, we store the submission of the previous frame here.
-    let mut previous_frame = Box::new(sync::now(device_.clone())) as Box<sync::GpuFuture>;
+    previous_frame: Box<sync::GpuFuture>,
+}
 
-    let mut prev_frame_start = time::Instant::now();
+impl VulkanRenderer {
+    fn from_device(device_: Arc<device::Device>, queue: Arc<device::Queue>, dimensions: [u32; 2],
+                   window: Option<WindowTarget>) -> Self {
+        let depth_buffer = image::attachment::AttachmentImage::transient(
+            device_.clone(), dimensions, format::D16Unorm).unwrap();
+
+        let buffers = ShapeBuffers {
+            index_buffers: HashMap::new(),
+            vertex_buffers: HashMap::new(),
+            uniforms: HashMap::new(),
+            uniform_pool: buffer::cpu_pool::CpuBufferPool::new(device_.clone(), buffer::BufferUsage::all()),
+        };
 
-    let mut dynamic_state = command_buffer::DynamicState {
-        line_width: None,
-        viewports: Some(vec![pipeline::viewport::Viewport {
-            origin: [0.0, 0.0],
-            dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-            depth_range: 0.0 .. 1.0,
-        }]),
-        scissors: None,
-    };
+        Self {
+            previous_frame: Box::new(sync::now(device_.clone())) as Box<sync::GpuFuture>,
+            device_,
+            queue,
+            dimensions,
+            background: [0., 0., 0., 1.],
+            depth_buffer,
+            buffers,
+            window,
+            offscreen: None,
+        }
+    }
 
-    loop {
-        // delta_time is inverse frame rate. Used for making movements and
-        // rotations dependent on time rather than frame rate.
-        let frame_start = time::Instant::now();
-        let delta_time_raw = frame_start - prev_frame_start;
-        let delta_time = delta_time_raw.as_secs() as f32 + delta_time_raw.subsec_nanos() as f32 * 1e-9;
-        prev_frame_start = frame_start;
+    pub fn windowed(events_loop: &winit::EventsLoop, title: &str,
+                    dimensions: [u32; 2]) -> Result<Self, String> {
+        // Open a window, and render to it. The window is sized from `dimensions`
+        // unless the platform decides otherwise; check `dimensions()`.
 
-        // It is important to call this function from time to time, otherwise resources will keep
-        // accumulating and you will eventually reach an out of memory error.
-        // Calling this function polls various fences in order to determine what the GPU has
-        // already processed, and frees the resources that are no longer needed.
-        previous_frame.cleanup_finished();
+        // When we create an instance, we have to pass a list of extensions that we want to enable.
+        //
+        // All the window-drawing functionalities are part of non-core extensions that we need
+        // to enable manually. To do so, we ask the `vulkano_win` crate for the list of extensions
+        // required to draw to a window.
+        let instance_ = instance::Instance::new(None, &vulkano_win::required_extensions(), None)
+            .map_err(|e| format!("failed to create Vulkan instance: {:?}", e))?;
+
+        // We then choose which physical device to use. We use the first device, which should work
+        // most of the time.
+        let physical = instance::PhysicalDevice::enumerate(&instance_)
+            .next().ok_or("no device available")?;
+        // Some little debug infos.
+        println!("Using device: {} (type: {:?})", physical.name(), physical.ty());
+
+        // This returns a `vulkano::swapchain::Surface` object that contains both a cross-platform winit
+        // window and a cross-platform Vulkan surface that represents the surface of the window.
+        // If you ever get an error about `build_vk_surface` being undefined, this probably means
+        // `VkSurfaceBuild` isn't imported.
+        let surface = winit::WindowBuilder::new().with_title(title)
+            .with_dimensions((dimensions[0], dimensions[1]).into())
+            .build_vk_surface(events_loop, instance_.clone())
+            .map_err(|e| format!("failed to create window: {:?}", e))?;
 
-        // If the swapchain needs to be recreated, recreate it
-        if recreate_swapchain {
-            // Get the new dimensions for the viewport/framebuffers.
-            dimensions = surface.capabilities(physical)
-                .expect("failed to get surface capabilities")
-                .current_extent.unwrap_or([WIDTH, HEIGHT]);
+        // We take the first queue that supports drawing to our window.
+        let queue_family = physical.queue_families().find(|&q| {
+            q.supports_graphics() && surface.is_supported(q).unwrap_or(false)
+        }).ok_or("couldn't find a graphical queue family")?;
 
-            let (new_swapchain, new_images) = match swapchain_.recreate_with_dimension(dimensions) {
-                Ok(r) => r,
-                // This error tends to happen when the user is manually resizing the window.
-                // Simply restarting the loop is the easiest way to fix this issue.
-                Err(swapchain::SwapchainCreationError::UnsupportedDimensions) => {
-                    continue;
-                },
-                Err(err) => panic!("{:?}", err)
-            };
+        // The only device extension we need is `khr_swapchain`, which allows us to draw to a window.
+        let device_ext = device::DeviceExtensions {
+            khr_swapchain: true,
+            .. device::DeviceExtensions::none()
+        };
 
-            swapchain_ = new_swapchain;
-            images = new_images;
+        let (device_, mut queues) = device::Device::new(
+            physical, physical.supported_features(), &device_ext, [(queue_family, 0.5)].iter().cloned()
+        ).map_err(|e| format!("failed to create device: {:?}", e))?;
+        let queue = queues.next().unwrap();
 
-            depth_buffer = image::attachment::AttachmentImage::transient(device_.clone(), dimensions,
-                                                                         format::D16Unorm).unwrap();
+        // Before we can draw on the surface, we have to create what is called a swapchain. Creating
+        // a swapchain allocates the color buffers that will contain the image that will ultimately
+        // be visible on the screen. These images are returned alongside with the swapchain.
 
-            framebuffers = None;
+        // Querying the capabilities of the surface. When we create the swapchain we can only
+        // pass values that are allowed by the capabilities.
+        let caps = surface.capabilities(physical)
+            .map_err(|e| format!("failed to get surface capabilities: {:?}", e))?;
 
-            dynamic_state.viewports = Some(vec![pipeline::viewport::Viewport {
-                origin: [0.0, 0.0],
-                dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-                depth_range: 0.0 .. 1.0,
-            }]);
+        // If `caps.current_extent` is `None`, the window size is determined by the
+        // dimensions of the swapchain.
+        let dimensions = caps.current_extent.unwrap_or(dimensions);
 
-            recreate_swapchain = false;
-        }
+        // The alpha mode indicates how the alpha value of the final image will behave. For example
+        // you can choose whether the window will be opaque or transparent.
+        let alpha = caps.supported_composite_alpha.iter().next().unwrap();
+        let format_ = caps.supported_formats[0].0;
 
-        // Because framebuffers contains an Arc on the old swapchain, we need to
-        // recreate framebuffers as well.
-        if framebuffers.is_none() {
-            framebuffers = Some(images.iter().map(|image| {
-                Arc::new(framebuffer::Framebuffer::start(render_pass.clone())
-                    .add(image.clone()).unwrap()
-                    .add(depth_buffer.clone()).unwrap()
-                    .build().unwrap())
-            }).collect::<Vec<_>>());
-        }
+        let (swapchain_, images) = swapchain::Swapchain::new(
+            device_.clone(), surface.clone(), caps.min_image_count, format_, dimensions, 1,
+            caps.supported_usage_flags, &queue, swapchain::SurfaceTransform::Identity, alpha,
+            swapchain::PresentMode::Fifo, true, None
+        ).map_err(|e| format!("failed to create swapchain: {:?}", e))?;
 
-        let mut uniform_buffer_subbuffers = HashMap::new();
+        let render_pass = make_render_pass(device_.clone(), swapchain_.format());
+        let pipeline_ = make_pipeline(device_.clone(), render_pass.clone());
 
-        // Cache these transforms here, so it doesn't updated each shape.
+        let window = WindowTarget {
+            surface, swapchain_, images, render_pass, pipeline_,
+            framebuffers: None,
+            recreate_swapchain: false,
+        };
 
-        let view = transforms::view(&scene.cam.position, &scene.cam.θ);
-        let proj = transforms::proj(&scene.cam);
+        Ok(Self::from_device(device_, queue, dimensions, Some(window)))
+    }
 
-//        println!("gl {:?}", transforms::proj_gl(&scene.cam));
-//        println!("my {:?}", transforms::proj(&scene.cam));
+    pub fn offscreen(dimensions: [u32; 2]) -> Result<Self, String> {
+        // Render to an image in GPU memory, without a window or swapchain. Errors
+        // if Vulkan or a suitable device isn't available.
+        let instance_ = instance::Instance::new(None, &instance::InstanceExtensions::none(), None)
+            .map_err(|e| format!("failed to create Vulkan instance: {:?}", e))?;
 
-        // todo temp
-        let inv_posit = ops::mul_arr(&scene.cam.position, -1.);
-        let inv_θ = ops::mul_arr(&scene.cam.θ, -1.);
+        let physical = instance::PhysicalDevice::enumerate(&instance_)
+            .next().ok_or("no device available")?;
 
-        for (shape_id, shape) in scene.shapes.clone() {  // todo remove this clone somehow!
-            uniform_buffer_subbuffers.insert(shape_id, {
-                let uniform_data = vs::ty::Data {
-                    // todo don't repeat things other than model here!!
-                    model: transforms::model(&shape.position, &shape.orientation, shape.scale),
+        let queue_family = physical.queue_families().find(|q| q.supports_graphics())
+            .ok_or("couldn't find a graphical queue family")?;
 
-                    // todo temp
-                    r_model: transforms::rotate(&shape.orientation),
-                    t_model: ops::transpose(transforms::translate(&shape.position)),
+        let (device_, mut queues) = device::Device::new(
+            physical, physical.supported_features(), &device::DeviceExtensions::none(),
+            [(queue_family, 0.5)].iter().cloned()
+        ).map_err(|e| format!("failed to create device: {:?}", e))?;
+        let queue = queues.next().unwrap();
 
-                    view,
-                    proj,
+        Ok(Self::from_device(device_, queue, dimensions, None))
+    }
 
-                    ambient_color: scene.lighting.ambient_color,
-                    diffuse_color: scene.lighting.diffuse_color,
-                    diffuse_direction: scene.lighting.diffuse_direction,
+    pub fn window(&self) -> Option<&winit::Window> {
+        self.window.as_ref().map(|w| w.surface.window())
+    }
 
-                    ambient_intensity: scene.lighting.ambient_intensity,
-                    diffuse_intensity: scene.lighting.diffuse_intensity,
-                    shape_opacity: 1.,
-                };
+    fn draw_to_window(&mut self) -> Result<(), String> {
+        // It is important to call this function from time to time, otherwise resources will keep
+        // accumulating and you will eventually reach an out of memory error.
+        // Calling this function polls various fences in order to determine what the GPU has
+        // already processed, and frees the resources that are no longer needed.
+        self.previous_frame.cleanup_finished();
 
-                uniform_buffer.next(uniform_data).unwrap()
-            });
+        let window = self.window.as_mut().unwrap();
+
+        if window.recreate_swapchain {
+            // Get the new dimensions for the viewport/framebuffers.
+            let dimensions = window.surface.capabilities(self.device_.physical_device())
+                .map_err(|e| format!("failed to get surface capabilities: {:?}", e))?
+                .current_extent.unwrap_or(self.dimensions);
+            // Minimized windows have no area; there's nothing to draw until
+            // they're restored.
+            if dimensions[0] == 0 || dimensions[1] == 0 {
+                return Ok(());
+            }
+
+            let (new_swapchain, new_images) = match window.swapchain_.recreate_with_dimension(dimensions) {
+                Ok(r) => r,
+                // This error tends to happen when the user is manually resizing the window.
+                // Skipping the frame is the easiest way to fix this issue.
+                Err(swapchain::SwapchainCreationError::UnsupportedDimensions) => return Ok(()),
+                Err(e) => return Err(format!("failed to recreate swapchain: {:?}", e)),
+            };
+
+            window.swapchain_ = new_swapchain;
+            window.images = new_images;
+            window.framebuffers = None;
+            window.recreate_swapchain = false;
+
+            if dimensions != self.dimensions {
+                self.dimensions = dimensions;
+                self.depth_buffer = image::attachment::AttachmentImage::transient(
+                    self.device_.clone(), dimensions, format::D16Unorm)
+                    .map_err(|e| format!("failed to create depth buffer: {:?}", e))?;
+            }
+        }
+
+        if window.framebuffers.is_none() {
+            let (render_pass, depth_buffer) = (&window.render_pass, &self.depth_buffer);
+            window.framebuffers = Some(window.images.iter().map(|image| {
+                Arc::new(framebuffer::Framebuffer::start(render_pass.clone())
+                    .add(image.clone()).unwrap()
+                    .add(depth_buffer.clone()).unwrap()
+                    .build().unwrap()) as Arc<framebuffer::FramebufferAbstract + Send + Sync>
+            }).collect());
         }
 
         // Before we can draw on the output, we have to *acquire* an image from the swapchain. If
         // no image is available (which happens if you submit draw commands too quickly), then the
         // function will block.
-        // This operation returns the index of the image that we are allowed to draw upon.
-        //
-        // This function can block if no image is available. The parameter is an optional timeout
-        // after which the function call will return an error.
-        let (image_num, acquire_future) = match swapchain::acquire_next_image(swapchain_.clone(), None) {
+        let (image_num, acquire_future) = match swapchain::acquire_next_image(window.swapchain_.clone(), None) {
             Ok(r) => r,
             Err(swapchain::AcquireError::OutOfDate) => {
-                recreate_swapchain = true;
-                continue;
+                window.recreate_swapchain = true;
+                return Ok(());
             },
-            Err(err) => panic!("{:?}", err)
+            Err(e) => return Err(format!("failed to acquire image: {:?}", e)),
         };
 
-        let mut command_buffer_ = command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
-                device_.clone(), queue.family()).unwrap()
-            // Before we can draw, we have to *enter a render pass*. There are two methods to do
-            // this: `draw_inline` and `draw_secondary`. The latter is a bit more advanced and is
-            // not covered here.
-            //
-            // The third parameter builds the list of values to clear the attachments with. The API
-            // is similar to the list of attachments when building the framebuffers, except that
-            // only the attachments that use `load: Clear` appear in the list.
+        // The third parameter of `begin_render_pass` is the list of values to clear the
+        // attachments with: color, then depth.
+        let command_buffer_ = command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
+                self.device_.clone(), self.queue.family()).unwrap()
             .begin_render_pass(
-                framebuffers.as_ref().unwrap()[image_num].clone(), false,
-                vec![
-                    [0.0, 0.0, 0.0, 1.0].into(),
-                    1f32.into()
-                ]
+                window.framebuffers.as_ref().unwrap()[image_num].clone(), false,
+                vec![self.background.into(), 1f32.into()]
             ).unwrap();
 
-        // todo this is probably the root of your performance prob. Reattack dimensions
-        // todo renderer when fixed.
-        for (shape_id, shape) in &scene.shapes {
-            let uniform_buffer_subbuffer = uniform_buffer_subbuffers[shape_id].clone();  // todo clone remove?
-
-            let set = Arc::new(descriptor::descriptor_set::PersistentDescriptorSet::start(pipeline_.clone(), 0)
-                .add_buffer(uniform_buffer_subbuffer).unwrap()
-                .build().unwrap()
-            );
-
-            // We are now inside the first subpass of the render pass. We add a draw command.
-            //
-            // The last two parameters contain the list of resources to pass to the shaders.
-            // Since we used an `EmptyPipeline` object, the objects have to be `()`.
-            command_buffer_ = command_buffer_.draw_indexed(
-                pipeline_.clone(),
-                &dynamic_state,
-                vertex_buffers[shape_id].clone(),
-                index_buffers[shape_id].clone(), set.clone(), ()
-            ).unwrap();
-        }
-
-        let final_cb = command_buffer_.end_render_pass().unwrap()
-
-            // We leave the render pass by calling `draw_end`. Note that if we had multiple
-            // subpasses we could have called `next_inline` (or `next_secondary`) to jump to the
-            // next subpass.
-            // Finish building the command buffer by calling `build`.
+        let final_cb = self.buffers.draw(command_buffer_, &window.pipeline_, self.dimensions)?
+            .end_render_pass().unwrap()
             .build().unwrap();
 
-        // In order to draw, we have to build a *command buffer*. The command buffer object holds
-        // the list of commands that are going to be executed.
-        //
-        // Building a command buffer is an expensive operation (usually a few hundred
-        // microseconds), but it is known to be a hot path in the driver and is expected to be
-        // optimized.
-        //
-        // Note that we have to pass a queue family when we create the command buffer. The command
-        // buffer will only be executable on that given queue family.
+        let previous_frame = mem::replace(&mut self.previous_frame,
+                                          Box::new(sync::now(self.device_.clone())) as Box<_>);
 
+        // `then_swapchain_present` submits a present command at the end of the queue, so the
+        // image is presented once the GPU has finished drawing it.
         let future = previous_frame.join(acquire_future)
-            .then_execute(queue.clone(), final_cb).unwrap()
-
-            // The color output is now expected to contain our triangle. But in order to show it on
-            // the screen, we have to *present* the image by calling `present`.
-            //
-            // This function does not actually present the image immediately. Instead it submits a
-            // present command at the end of the queue. This means that it will only be presented once
-            // the GPU has finished executing the command buffer that draws the triangle.
-            .then_swapchain_present(queue.clone(), swapchain_.clone(), image_num)
+            .then_execute(self.queue.clone(), final_cb).unwrap()
+            .then_swapchain_present(self.queue.clone(), window.swapchain_.clone(), image_num)
             .then_signal_fence_and_flush();
 
         match future {
             Ok(future) => {
-                previous_frame = Box::new(future) as Box<_>;
+                self.previous_frame = Box::new(future) as Box<_>;
+                Ok(())
             }
             Err(sync::FlushError::OutOfDate) => {
-                recreate_swapchain = true;
-                previous_frame = Box::new(sync::now(device_.clone())) as Box<_>;
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                previous_frame = Box::new(sync::now(device_.clone())) as Box<_>;
+                window.recreate_swapchain = true;
+                Ok(())
             }
+            Err(e) => Err(format!("failed to present frame: {:?}", e)),
         }
+    }
 
-        // Rotate scene.shapes.
-        // todo add back in rotation speed.
-//        for (id, shape) in &mut scene.shapes {
-//            shape.orientation += &(&shape.rotation_speed * delta_time);
-//        }
+    fn draw_offscreen(&mut self, output: Option<Arc<CpuAccessibleBuffer<[u8]>>>) -> Result<(), String> {
+        // Draw to our offscreen image, optionally copying the result to `output`, and
+        // wait until the GPU's done.
+        let stale = match self.offscreen {
+            Some(ref target) => target.dimensions != self.dimensions,
+            None => true,
+        };
+        if stale {
+            self.offscreen = Some(OffscreenTarget::new(
+                self.device_.clone(), self.dimensions, self.depth_buffer.clone())?);
+        }
+        let target = self.offscreen.as_ref().unwrap();
+
+        let command_buffer_ = command_buffer::AutoCommandBufferBuilder::primary_one_time_submit(
+                self.device_.clone(), self.queue.family())
+            .map_err(|e| format!("failed to create command buffer: {:?}", e))?
+            .begin_render_pass(target.framebuffer_.clone(), false,
+                               vec![self.background.into(), 1f32.into()])
+            .map_err(|e| format!("failed to begin render pass: {:?}", e))?;
+
+        let mut command_buffer_ = self.buffers.draw(command_buffer_, &target.pipeline_, self.dimensions)?
+            .end_render_pass()
+            .map_err(|e| format!("failed to end render pass: {:?}", e))?;
+
+        if let Some(output) = output {
+            command_buffer_ = command_buffer_
+                .copy_image_to_buffer(target.color_image.clone(), output)
+                .map_err(|e| format!("failed to copy image: {:?}", e))?;
+        }
+        let command_buffer_ = command_buffer_.build()
+            .map_err(|e| format!("failed to build command buffer: {:?}", e))?;
+
+        let previous_frame = mem::replace(&mut self.previous_frame,
+                                          Box::new(sync::now(self.device_.clone())) as Box<_>);
+
+        previous_frame
+            .then_execute(self.queue.clone(), command_buffer_)
+            .map_err(|e| format!("failed to submit commands: {:?}", e))?
+            .then_signal_fence_and_flush()
+            .map_err(|e| format!("failed to submit commands: {:?}", e))?
+            .wait(None)
+            .map_err(|e| format!("failed to render: {:?}", e))
+    }
+}
 
-        // Note that in more complex programs it is likely that one of `acquire_next_image`,
-        // `command_buffer::submit`, or `present` will block for some time. This happens when the
-        // GPU's queue is full and the driver has to wait until the GPU finished some work.
-        //
-        // Unfortunately the Vulkan API doesn't provide any way to not wait or to detect when a
-        // wait would happen. Blocking may be the desired behavior, but if you don't want to
-        // block you should spawn a separate thread dedicated to submissions.
+impl Renderer for VulkanRenderer {
+    fn upload_scene(&mut self, scene: &Scene) {
+        let (index_buffers, vertex_buffers) = make_static_buffers(&scene.shapes, self.device_.clone());
+        self.buffers.index_buffers = index_buffers;
+        self.buffers.vertex_buffers = vertex_buffers;
+        self.update_uniforms(scene);
+    }
+
+    fn update_uniforms(&mut self, scene: &Scene) {
+        self.buffers.uniforms = scene_uniforms(scene, &scene.cam);
+    }
+
+    fn set_background(&mut self, color: [f32; 4]) {
+        self.background = color;
+    }
+
+    fn draw_frame(&mut self) -> Result<(), String> {
+        if self.window.is_some() {
+            self.draw_to_window()
+        } else {
+            self.draw_offscreen(None)
+        }
+    }
+
+    fn resize(&mut self, dimensions: [u32; 2]) {
+        // Windows may end up a different size; the swapchain is recreated to
+        // match the surface before the next frame. Zero-area sizes, eg from
+        // minimizing, are ignored.
+        if dimensions[0] == 0 || dimensions[1] == 0 {
+            return;
+        }
+        if let Some(ref mut window) = self.window {
+            window.recreate_swapchain = true;
+        }
+        if dimensions != self.dimensions {
+            self.dimensions = dimensions;
+            self.depth_buffer = image::attachment::AttachmentImage::transient(
+                self.device_.clone(), dimensions, format::D16Unorm).unwrap();
+        }
+    }
+
+    fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+
+    fn read_pixels(&mut self) -> Result<Vec<u8>, String> {
+        // Windowed renderers draw a separate frame to an offscreen image for this,
+        // since swapchain images aren't readable.
+        let output = CpuAccessibleBuffer::from_iter(
+            self.device_.clone(), buffer::BufferUsage::all(),
+            (0 .. self.dimensions[0] * self.dimensions[1] * 4).map(|_| 0u8)
+        ).map_err(|e| format!("failed to create output buffer: {:?}", e))?;
+
+        self.draw_offscreen(Some(output.clone()))?;
+
+        let pixels = output.read().map_err(|e| format!("failed to read image: {:?}", e))?;
+        Ok(pixels.to_vec())
+    }
+}
+
+pub fn render(scene: Scene) {
+    // Open a window showing a scene, and handle input until it's closed. The
+    // camera's aspect ratio is kept in sync with the window. Applications that
+    // need their own loop can drive a `VulkanRenderer` directly.
+    render_with_bindings(scene, Bindings::default(), [0., 0., 0., 1.])
+}
+
+pub fn render_with_bindings(mut scene: Scene, bindings: Bindings, background: [f32; 4]) {
+    // As `render`, with keys bound to actions by `bindings`, and a background color.
+    let mut events_loop = winit::EventsLoop::new();
+
+    let mut renderer = VulkanRenderer::windowed(&events_loop, TITLE, [WIDTH, HEIGHT])
+        .expect("failed to set up the renderer");
+
+    renderer.set_background(background);
+    renderer.upload_scene(&scene);
+
+    let mut input_state = input::InputState::default();
+    let mut hovered = None;  // Atom index.
+    let mut prev_title = TITLE.to_string();
+
+    let mut prev_frame_start = time::Instant::now();
+
+    loop {
+        // delta_time is inverse frame rate. Used for making movements and
+        // rotations dependent on time rather than frame rate.
+        let frame_start = time::Instant::now();
+        let delta_time_raw = frame_start - prev_frame_start;
+        let delta_time = delta_time_raw.as_secs() as f32 + delta_time_raw.subsec_nanos() as f32 * 1e-9;
+        prev_frame_start = frame_start;
+
+        // The window's size may have changed while drawing the last frame.
+        let dimensions = renderer.dimensions();
+        if dimensions[0] > 0 && dimensions[1] > 0 {
+            scene.cam.aspect = dimensions[0] as f32 / dimensions[1] as f32;
+
+            renderer.update_uniforms(&scene);
+            if let Err(e) = renderer.draw_frame() {
+                println!("{}", e);
+            }
+        }
 
         // Handling the window events in order to close the program when the user wants to close
         // it.
         let mut done = false;
+        let mut resized = None;
+        let mut just_pressed = Vec::new();
+        let mut mouse_events = Vec::new();
+
+        // winit reports sizes and positions in logical pixels; we use physical ones.
+        let hidpi_factor = renderer.window().unwrap().get_hidpi_factor();
+
+        {
+            let currently_pressed = &mut input_state.keys;
+            let shift = &mut input_state.shift;
+
+            events_loop.poll_events(|ev| {
+                let event = match ev {
+                    winit::Event::WindowEvent { event, .. } => event,
+                    _ => return,
+                };
 
-        events_loop.poll_events(|ev| {
-            match ev {
-                winit::Event::WindowEvent { event: winit::WindowEvent::CloseRequested, .. } => done = true,
+                match event {
+                    winit::WindowEvent::CloseRequested => done = true,
 
-                winit::Event::WindowEvent {
-                    event: winit::WindowEvent::KeyboardInput {
+                    winit::WindowEvent::Resized(size) => resized = Some(size),
+
+                    winit::WindowEvent::KeyboardInput {
                         input: winit::KeyboardInput{
                             // We use Scancode rather than virtual_keycode, since
                             // it's tied to physical key location rather than semantics;
@@ -610,22 +715,112 @@ pub fn render() {
                             // use this approach.
                             scancode: code,
                             state: el_state,
+                            modifiers,
                             ..
                         }, ..
-                    }, ..
-                } => match el_state {
-                    winit::ElementState::Pressed => {
-                        if !currently_pressed.contains(&code) { currently_pressed.push(code) }
+                    } => {
+                        *shift = modifiers.shift;
+                        match el_state {
+                            winit::ElementState::Pressed => {
+                                if !currently_pressed.contains(&code) {
+                                    currently_pressed.push(code);
+                                    just_pressed.push(code);
+                                }
+                            },
+                            winit::ElementState::Released => {
+                                currently_pressed.remove_item(&code);
+                            }
+                        }
                     },
-                    winit::ElementState::Released => {
-                        currently_pressed.remove_item(&code);
-                    }
-                },
-                _ => ()
-            }
-        });
+
+                    winit::WindowEvent::CursorMoved { position, modifiers, .. } => {
+                        *shift = modifiers.shift;
+                        let position = position.to_physical(hidpi_factor);
+                        mouse_events.push(input::MouseEvent::Moved([position.x as f32, position.y as f32]));
+                    },
+
+                    winit::WindowEvent::MouseInput { state: el_state, button, modifiers, .. } => {
+                        *shift = modifiers.shift;
+                        let button = match button {
+                            winit::MouseButton::Left => input::MouseButton::Left,
+                            winit::MouseButton::Right => input::MouseButton::Right,
+                            winit::MouseButton::Middle => input::MouseButton::Middle,
+                            winit::MouseButton::Other(_) => return,
+                        };
+                        mouse_events.push(match el_state {
+                            winit::ElementState::Pressed => input::MouseEvent::Pressed(button),
+                            winit::ElementState::Released => input::MouseEvent::Released(button),
+                        });
+                    },
+
+                    winit::WindowEvent::MouseWheel { delta, .. } => {
+                        // Touchpads scroll by pixels; treat 20 as a line.
+                        let lines = match delta {
+                            winit::MouseScrollDelta::LineDelta(_, y) => y,
+                            winit::MouseScrollDelta::PixelDelta(p) => p.y as f32 / 20.,
+                        };
+                        mouse_events.push(input::MouseEvent::Scrolled(lines));
+                    },
+
+                    _ => ()
+                }
+            });
+        }
         if done { return; }
 
-        input::handle_pressed(&currently_pressed, delta_time, &mut scene);
+        if let Some(size) = resized {
+            // Minimized windows report a size of 0; keep the old size until they're restored.
+            let size = size.to_physical(hidpi_factor);
+            if size.width >= 1. && size.height >= 1. {
+                renderer.resize([size.width as u32, size.height as u32]);
+            }
+        }
+
+        let representation = scene.representation;
+        for code in &just_pressed {
+            input::handle_key_down(*code, &bindings, &mut scene);
+        }
+        if scene.representation != representation {
+            renderer.upload_scene(&scene);
+        }
+
+        for event in mouse_events {
+            input::handle_mouse(event, &mut input_state, renderer.dimensions(), &mut scene);
+        }
+        // Left clicks select atoms, and shift-drags box-select them. Changes to
+        // the selection change its measurement lines, so re-upload.
+        let selection = scene.selection.clone();
+        for click in input_state.take_clicks() {
+            if click.button == input::MouseButton::Left {
+                let atom = pick::pick(&scene, click.position, renderer.dimensions()).map(|h| h.atom);
+                selection::click(&mut scene, atom, click.shift);
+            }
+        }
+        for (from, to) in input_state.take_boxes() {
+            selection::box_select(&mut scene, from, to, renderer.dimensions());
+        }
+        if scene.selection != selection {
+            renderer.upload_scene(&scene);
+        }
+
+        // Show the atom under the cursor, and the selection, in the title bar.
+        // Hovering isn't updated while dragging.
+        if input_state.buttons.is_empty() {
+            hovered = input_state.cursor.and_then(|c| pick::pick(&scene, c, renderer.dimensions()))
+                .map(|h| h.atom);
+        }
+        let mut title = TITLE.to_string();
+        if let (Some(i), Some(molecule)) = (hovered, &scene.molecule) {
+            title += &format!(" - {}", pick::describe(molecule, i));
+        }
+        if let Some(status) = selection::status(&scene) {
+            title += &format!(" | Selected: {}", status);
+        }
+        if title != prev_title {
+            renderer.window().unwrap().set_title(&title);
+            prev_title = title;
+        }
+
+        input::handle_pressed(&input_state.keys, &bindings, delta_time, &mut scene);
     }
 }
@@ -0,0 +1,67 @@
+// The interface between scenes and rendering backends. Backends are our
+// Vulkan renderer (`render::VulkanRenderer`), and the CPU rasterizer
+// (`raster::SoftwareRenderer`).
+//
+// Applications own the loop: upload a scene once (and again when its shapes
+// change, eg a new representation), then each frame update uniforms from the
+// scene and draw. A typical frame:
+//
+//     renderer.update_uniforms(&scene);
+//     renderer.draw_frame()?;
+
+use std::collections::HashMap;
+
+use ops::transpose;
+use transforms;
+use types::{Camera, Scene, Uniforms};
+
+pub trait Renderer {
+    // Make vertex and index buffers for the scene's shapes, replacing any from
+    // a previous scene. Also updates uniforms.
+    fn upload_scene(&mut self, scene: &Scene);
+
+    // Update per-shape uniforms from the scene's camera, lighting, and shape
+    // positions and orientations. Cheap; call every frame.
+    fn update_uniforms(&mut self, scene: &Scene);
+
+    fn set_background(&mut self, color: [f32; 4]);
+
+    // Draw the uploaded shapes, using the latest uniforms. Windowed renderers
+    // present the result.
+    fn draw_frame(&mut self) -> Result<(), String>;
+
+    // Change the size of the output, in pixels. Keep the camera's aspect ratio
+    // in sync with `dimensions()`.
+    fn resize(&mut self, dimensions: [u32; 2]);
+
+    fn dimensions(&self) -> [u32; 2];
+
+    // Draw a frame, and return it as RGBA pixels, 8 bits per channel, starting
+    // with the top row.
+    fn read_pixels(&mut self) -> Result<Vec<u8>, String>;
+}
+
+pub fn scene_uniforms(scene: &Scene, cam: &Camera) -> HashMap<u32, Uniforms> {
+    // Uniforms for each shape, keyed by shape id. Shared by backends.
+    // Cache these transforms here, so they're not recomputed for each shape.
+    let view = transforms::view(&cam.position, &cam.orientation);
+    let proj = transforms::proj(cam);
+
+    scene.shapes.iter()
+        .map(|(id, shape)| {
+            let mut uniforms = Uniforms::new(shape, view, proj, &scene.lighting);
+            if shape.billboard {
+                let model = transforms::billboard(&shape.position, &cam.orientation, shape.scale);
+                uniforms.model = transpose(model);
+                uniforms.r_model = transpose(cam.orientation.to_matrix());
+                // Light them from the camera too, so they're lit the same from any side.
+                uniforms.diffuse_direction = cam.forward();
+            }
+            // Atom shapes' ids are their atoms' indices; see `scenes::molecule_shapes`.
+            if scene.molecule.is_some() && scene.selection.contains(&(*id as usize)) {
+                uniforms.highlight = 1.;
+            }
+            (*id, uniforms)
+        })
+        .collect()
+}
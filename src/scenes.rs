@@ -2,11 +2,29 @@
 use std::collections::HashMap;
 use std::f32::consts::PI;
 
+use elements;
+use orbit;
+use selection;
+use molecule::{Bond, Molecule};
+use ops::sub_arr;
 use shape_maker;
-use types::{Camera, Lighting, Scene, Shape, CameraType};
+use transforms;
+use types::{BondOrder, Camera, Lighting, Mesh, Orbit, Quaternion, Representation, Scene, Shape, CameraType};
 
 const τ: f32 = 2. * PI;
 
+// In ball-and-stick, atoms are drawn as spheres at this fraction of their
+// covalent radius.
+const ATOM_SCALE: f32 = 0.6;
+const BOND_RADIUS: f32 = 0.12;
+const LICORICE_RADIUS: f32 = 0.2;
+const WIRE_RADIUS: f32 = 0.03;
+// Molecules with more atoms than this draw all their atoms as one shape, and
+// all their bonds as another, since a draw call per atom is slow for proteins.
+const BATCH_ATOMS: usize = 1000;
+// The largest ring multiple bonds are drawn towards the inside of.
+const MAX_RING: usize = 8;
+
 const base_lighting: Lighting = Lighting {
         ambient_intensity: 0.8,
         diffuse_intensity: 0.6,
@@ -18,33 +36,280 @@ const base_lighting: Lighting = Lighting {
 
 const base_camera: Camera = Camera {
     position: [0., 0., -7.],
-    θ: [0., 0., 0.],
+    orientation: Quaternion { w: 1., x: 0., y: 0., z: 0. },
     fov: τ / 4.,
     aspect: 4./3.,
     near: 0.1,
     far: 100.,
 };
 
-fn make_scene(aspect: f32, shapes: Vec<Shape>) -> Scene {
+pub fn make_scene(aspect: f32, shapes: Vec<Shape>) -> Scene {
+    // A scene with our default camera and lighting. Shape ids are indices into `shapes`.
     let shapes: HashMap<u32, Shape> = shapes.into_iter().enumerate()
         .map(|(i, shape)| (i as u32, shape)).collect();
 
     Scene {
         shapes,
-        cam: base_camera,
+        cam: Camera { aspect, ..base_camera },
         cam_type: CameraType::Free,
+        // Matches the camera's default position.
+        orbit: Orbit { target: [0., 0., 0.], distance: 7. },
         lighting: base_lighting,
         sensitivities: (2., 2., 0.2),
+        molecule: None,
+        representation: Representation::BallAndStick,
+        selection: Vec::new(),
+    }
+}
+
+
+pub fn make_nucleus(protons: u8, _neutrons: u8, position: [f32; 3]) -> Shape {
+    let element = elements::by_number(protons).unwrap_or(&elements::UNKNOWN);
+    Shape::new(
+        shape_maker::icosphere(element.covalent_radius * ATOM_SCALE, 2, element.color()),
+        position, [0., 0., 0.]
+    )
+}
+
+pub fn make_atom(element: &str, position: [f32; 3]) -> Shape {
+    let element = elements::lookup(element);
+    Shape::new(
+        shape_maker::icosphere(element.covalent_radius * ATOM_SCALE, 2, element.color()),
+        position, [0., 0., 0.]
+    )
+}
+
+pub fn make_bond(start: [f32; 3], end: [f32; 3], order: BondOrder,
+                 colors: ([f32; 4], [f32; 4]), side: [f32; 3]) -> Shape {
+    // Sticks between two atom centers, half-colored by each atom. The shape is
+    // positioned at the bond's midpoint. See `shape_maker::bond_sticks` for `side`.
+    let mid = [(start[0] + end[0]) / 2., (start[1] + end[1]) / 2., (start[2] + end[2]) / 2.];
+    let mesh = shape_maker::bond_sticks(
+        [start[0] - mid[0], start[1] - mid[1], start[2] - mid[2]],
+        [end[0] - mid[0], end[1] - mid[1], end[2] - mid[2]],
+        order, BOND_RADIUS, 12, colors, side
+    );
+    Shape::new(mesh, mid, [0., 0., 0.])
+}
+
+fn in_ring(adjacency: &[Vec<usize>], atom: usize, from: usize, to: usize) -> bool {
+    // Whether `atom`, bonded to `from`, is in a ring with the `from`-`to` bond;
+    // ie it reaches `to` without going through `from`. Only small rings count.
+    let mut visited = vec![from, atom];
+    let mut frontier = vec![atom];
+    for _ in 0..MAX_RING - 2 {
+        let mut next = Vec::new();
+        for &i in &frontier {
+            for &n in &adjacency[i] {
+                if n == to {
+                    return true;
+                }
+                if !visited.contains(&n) {
+                    visited.push(n);
+                    next.push(n);
+                }
+            }
+        }
+        frontier = next;
+    }
+    false
+}
+
+fn bond_side(molecule: &Molecule, adjacency: &[Vec<usize>], bond: &Bond) -> [f32; 3] {
+    // Multiple-bond sticks are offset towards another atom bonded to either end,
+    // so they lie in the plane of the molecule, eg a ring. Prefer an atom in a
+    // ring with the bond, then one bonded to others, over a terminal atom, eg H.
+    let (a, b) = bond.atoms;
+    let candidates = adjacency[a].iter().filter(|n| **n != b).map(|n| (*n, a, b))
+        .chain(adjacency[b].iter().filter(|n| **n != a).map(|n| (*n, b, a)));
+
+    let mut best: Option<(usize, usize, u8)> = None;
+    for (n, from, to) in candidates {
+        let score = if in_ring(adjacency, n, from, to) {
+            2
+        } else if adjacency[n].len() >= 2 {
+            1
+        } else {
+            0
+        };
+        if best.map_or(true, |(_, _, s)| score > s) {
+            best = Some((n, from, score));
+        }
+    }
+
+    match best {
+        Some((n, from, _)) => sub_arr(&molecule.atoms[n].position, &molecule.atoms[from].position),
+        None => [0., 0., 0.],
+    }
+}
+
+pub fn atom_positions(molecule: &Molecule) -> Vec<[f32; 3]> {
+    // Atom positions as drawn: centered on the molecule's centroid.
+    let center = molecule.centroid();
+    molecule.atoms.iter()
+        .map(|a| [a.position[0] - center[0], a.position[1] - center[1], a.position[2] - center[2]])
+        .collect()
+}
+
+pub fn atom_radius(element: &str, representation: Representation) -> f32 {
+    // The radius atoms are drawn at. In licorice and wireframe, bonded atoms are
+    // drawn as the rounded ends of their bonds, at about this radius.
+    match representation {
+        Representation::BallAndStick => elements::lookup(element).covalent_radius * ATOM_SCALE,
+        Representation::SpaceFilling => elements::lookup(element).vdw_radius,
+        Representation::Licorice => LICORICE_RADIUS,
+        Representation::Wireframe => WIRE_RADIUS * 2.,
+    }
+}
+
+pub fn bond_radius(representation: Representation) -> Option<f32> {
+    // The radius bonds are drawn at; None if they're not drawn. Multiple bonds
+    // in ball-and-stick are several sticks of this radius.
+    match representation {
+        Representation::BallAndStick => Some(BOND_RADIUS),
+        Representation::SpaceFilling => None,
+        Representation::Licorice => Some(LICORICE_RADIUS),
+        Representation::Wireframe => Some(WIRE_RADIUS),
+    }
+}
+
+pub fn atom_spheres(molecule: &Molecule, representation: Representation) -> Vec<bool> {
+    // Whether each atom is drawn as a sphere. In licorice and wireframe, bonded
+    // atoms are drawn as their bonds' rounded ends instead.
+    match representation {
+        Representation::BallAndStick | Representation::SpaceFilling => {
+            vec![true; molecule.atoms.len()]
+        }
+        Representation::Licorice | Representation::Wireframe => {
+            molecule.adjacency().iter().map(|n| n.is_empty()).collect()
+        }
+    }
+}
+
+pub fn atom_sphere(element: &str, position: [f32; 3], radius: f32) -> Shape {
+    let color = elements::lookup(element).color();
+    Shape::new(shape_maker::icosphere(radius, 2, color), position, [0., 0., 0.])
+}
+
+pub fn batched(molecule: &Molecule) -> bool {
+    molecule.atoms.len() > BATCH_ATOMS
+}
+
+pub fn batch_id(molecule: &Molecule) -> u32 {
+    // The id of a batched molecule's atoms shape; its bonds shape's is next.
+    (molecule.atoms.len() + molecule.bonds.len()) as u32
+}
+
+fn batch(shapes: &[&Shape]) -> Shape {
+    // One shape with all of these shapes' meshes, as placed in the world.
+    let meshes: Vec<_> = shapes.iter()
+        .map(|s| (&s.mesh, transforms::model(&s.position, &s.orientation, s.scale)))
+        .collect();
+    Shape::new(shape_maker::combine_meshes(&meshes), [0., 0., 0.], [0., 0., 0.])
+}
+
+pub fn molecule_shapes(molecule: &Molecule, representation: Representation) -> HashMap<u32, Shape> {
+    // Shapes for a molecule, centered on its centroid. Atom shapes' ids are the
+    // atoms' indices; bond shapes follow. Atoms that are only drawn as bond ends,
+    // eg in licorice, have no shape unless they have no bonds. Large molecules
+    // are batched instead; see `batch_id`.
+    let shapes = unbatched_shapes(molecule, representation);
+    if !batched(molecule) {
+        return shapes;
+    }
+
+    let mut ids: Vec<&u32> = shapes.keys().collect();
+    ids.sort();
+    let (atoms, bonds): (Vec<&u32>, Vec<&u32>) = ids.into_iter()
+        .partition(|id| (**id as usize) < molecule.atoms.len());
+
+    let mut result = HashMap::new();
+    for (i, ids) in [atoms, bonds].iter().enumerate() {
+        if !ids.is_empty() {
+            let group: Vec<&Shape> = ids.iter().map(|id| &shapes[id]).collect();
+            result.insert(batch_id(molecule) + i as u32, batch(&group));
+        }
+    }
+    result
+}
+
+fn unbatched_shapes(molecule: &Molecule, representation: Representation) -> HashMap<u32, Shape> {
+    let positions = atom_positions(molecule);
+    let centered = |i: usize| positions[i];
+    let color = |i: usize| elements::lookup(&molecule.atoms[i].element).color();
+    // Atoms of an element share a sphere mesh; building one per atom is slow
+    // for large molecules.
+    let mut spheres: HashMap<&str, Mesh> = HashMap::new();
+    let mut sphere = |i: usize, radius: f32| {
+        let element: &str = &molecule.atoms[i].element;
+        let mesh = spheres.entry(element)
+            .or_insert_with(|| shape_maker::icosphere(radius, 2, elements::lookup(element).color()));
+        Shape::new(mesh.clone(), centered(i), [0., 0., 0.])
+    };
+
+    let adjacency = molecule.adjacency();
+
+    let mut shapes = HashMap::new();
+    for (i, drawn) in atom_spheres(molecule, representation).into_iter().enumerate() {
+        if drawn {
+            shapes.insert(i as u32, sphere(i, atom_radius(&molecule.atoms[i].element, representation)));
+        }
+    }
+
+    if representation == Representation::SpaceFilling {
+        return shapes;
     }
+
+    for (i, bond) in molecule.bonds.iter().enumerate() {
+        let (a, b) = bond.atoms;
+        let (start, end) = (centered(a), centered(b));
+        let mid = [(start[0] + end[0]) / 2., (start[1] + end[1]) / 2., (start[2] + end[2]) / 2.];
+        let rel = |p: [f32; 3]| [p[0] - mid[0], p[1] - mid[1], p[2] - mid[2]];
+
+        let shape = match representation {
+            Representation::Licorice => Shape::new(shape_maker::split_capsule(
+                rel(start), rel(end), LICORICE_RADIUS, 12, (color(a), color(b))
+            ), mid, [0., 0., 0.]),
+            Representation::Wireframe => Shape::new(shape_maker::split_cylinder(
+                rel(start), rel(end), WIRE_RADIUS, 4, (color(a), color(b))
+            ), mid, [0., 0., 0.]),
+            _ => {
+                // Single bonds are one stick, with no side to offset towards.
+                let side = match bond.order {
+                    BondOrder::Single => [0., 0., 0.],
+                    _ => bond_side(molecule, &adjacency, bond),
+                };
+                make_bond(start, end, bond.order, (color(a), color(b)), side)
+            }
+        };
+        shapes.insert((molecule.atoms.len() + i) as u32, shape);
+    }
+    shapes
 }
 
+pub fn from_molecule(aspect: f32, molecule: &Molecule, representation: Representation) -> Scene {
+    // Molecules get an orbit camera, backed off until the whole molecule fits in view.
+    let mut scene = make_scene(aspect, Vec::new());
+    scene.molecule = Some(molecule.clone());
+    scene.cam_type = CameraType::Orbit;
+    set_representation(&mut scene, representation);
 
-pub fn make_nucleus(protons: u8, neutrons: u8, position: [f32; 3]) -> Shape {
-    // todo returns a cube; change to sphere
-//    let size = (protons + neutrons) as f32 * 0.2
-    // todo temp
-    let size = 1.;
-    Shape::new(shape_maker::cube(size), position, [0., 0., 0.])
+    // Fit the van der Waals spheres, so other representations fit too. Atoms
+    // are drawn centered on the centroid.
+    let radius = atom_positions(molecule).iter().zip(&molecule.atoms).map(|(p, a)| {
+        (p[0].powi(2) + p[1].powi(2) + p[2].powi(2)).sqrt() + elements::lookup(&a.element).vdw_radius
+    }).fold(0., f32::max);
+    orbit::fit(&mut scene.cam, &mut scene.orbit, [0., 0., 0.], radius);
+    scene
+}
+
+pub fn set_representation(scene: &mut Scene, representation: Representation) {
+    // Rebuild the scene's shapes from its molecule, if it has one.
+    scene.representation = representation;
+    if let Some(ref molecule) = scene.molecule {
+        scene.shapes = molecule_shapes(molecule, representation);
+    }
+    selection::add_measurement_shapes(scene);
 }
 
 //pub fn make_electron(protons: u8, neutrons: u8, position: [f32; 3]) -> Shape {
@@ -61,3 +326,89 @@ pub fn scene_1(aspect: f32) -> Scene {
     ])
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use molecule::{Atom, Bond};
+
+    #[test]
+    fn representations() {
+        let mut water = Molecule::new("water", vec![
+            Atom::new("O", [0., 0., 0.117]),
+            Atom::new("H", [0., 0.757, -0.467]),
+            Atom::new("H", [0., -0.757, -0.467]),
+            Atom::new("Na", [6., 0., 0.]),
+        ], Vec::new());
+        water.perceive_bonds();
+
+        let count = |rep| {
+            let shapes = molecule_shapes(&water, rep);
+            (shapes.keys().filter(|k| **k < 4).count(), shapes.keys().filter(|k| **k >= 4).count())
+        };
+        assert_eq!(count(Representation::BallAndStick), (4, 2));
+        assert_eq!(count(Representation::SpaceFilling), (4, 0));
+        // Only the unbonded sodium gets its own shape.
+        assert_eq!(count(Representation::Licorice), (1, 2));
+        assert_eq!(count(Representation::Wireframe), (1, 2));
+
+        let mut scene = from_molecule(1., &water, Representation::BallAndStick);
+        set_representation(&mut scene, Representation::Licorice);
+        assert_eq!(scene.shapes.len(), 3);
+
+        // The sodium's 6 Å away; the camera backs off to keep it in view.
+        assert!(-scene.cam.position[2] > 7.);
+    }
+
+    #[test]
+    fn ring_sides() {
+        // Benzene, with C-H bonds listed first. Aromatic sticks are offset
+        // towards the ring, not the hydrogens.
+        let mut atoms = Vec::new();
+        for r in &[1.4, 2.48] {
+            for i in 0..6 {
+                let θ = i as f32 * τ / 6.;
+                atoms.push(Atom::new(if *r < 2. { "C" } else { "H" }, [r * θ.cos(), r * θ.sin(), 0.]));
+            }
+        }
+        let mut bonds: Vec<Bond> = (0..6).map(|i| Bond::new(i, i + 6, BondOrder::Single)).collect();
+        bonds.extend((0..6).map(|i| Bond::new(i, (i + 1) % 6, BondOrder::Aromatic)));
+        let benzene = Molecule::new("benzene", atoms, bonds);
+        let adjacency = benzene.adjacency();
+
+        assert!(in_ring(&adjacency, 5, 0, 1));
+        assert!(!in_ring(&adjacency, 6, 0, 1));
+        let side = bond_side(&benzene, &adjacency, &benzene.bonds[6]);
+        let to_center = [-1.4, 0., 0.];  // From the first carbon.
+        assert!(side[0] * to_center[0] + side[1] * to_center[1] > 0.);
+    }
+
+    #[test]
+    fn batching() {
+        // A 11 x 10 x 10 grid of hydrogens, with one bond.
+        let atoms = (0..1100).map(|i| {
+            Atom::new("H", [(i % 10) as f32 * 3., (i / 10 % 10) as f32 * 3., (i / 100) as f32 * 3.])
+        }).collect();
+        let molecule = Molecule::new("grid", atoms, vec![Bond::new(0, 1, BondOrder::Single)]);
+        let id = batch_id(&molecule);
+
+        let shapes = molecule_shapes(&molecule, Representation::BallAndStick);
+        let mut ids: Vec<&u32> = shapes.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec![&id, &(id + 1)]);
+        let single = shape_maker::icosphere(atom_radius("H", Representation::BallAndStick), 2, [1.; 4]);
+        assert_eq!(shapes[&id].mesh.tris.len(), 1100 * single.tris.len());
+
+        // Selected atoms get shells to highlight, which go with the selection.
+        let mut scene = from_molecule(1., &molecule, Representation::BallAndStick);
+        selection::click(&mut scene, Some(5), false);
+        selection::click(&mut scene, Some(6), true);
+        assert!(scene.shapes.contains_key(&5) && scene.shapes.contains_key(&6));
+        assert!(scene.shapes.contains_key(&(id + 2)));  // The distance between them.
+        assert_eq!(::renderer::scene_uniforms(&scene, &scene.cam)[&5].highlight, 1.);
+
+        selection::click(&mut scene, Some(6), false);
+        assert!(!scene.shapes.contains_key(&5));
+        assert_eq!(scene.shapes.len(), 3);
+    }
+}
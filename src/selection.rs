@@ -0,0 +1,304 @@
+// Selecting atoms, and measuring between them. Selected atoms are highlighted
+// (see `renderer::scene_uniforms`). With 2, 3, or 4 atoms selected, we measure
+// the distance, angle, or dihedral between them, in selection order, and draw
+// dashed lines along the measured path, labeled with the measurement.
+
+use std::fmt;
+
+use molecule::Molecule;
+use ops::{add_arr, cross, dot, dot3, dot_v, mul_arr, normalize, sub_arr};
+use pick;
+use scenes;
+use shape_maker;
+use transforms;
+use types::{Scene, Shape};
+
+const MEASURE_COLOR: [f32; 4] = [1., 0.85, 0.2, 1.];
+const DASH_RADIUS: f32 = 0.025;
+const DASH_LENGTH: f32 = 0.12;  // Å
+const LABEL_HEIGHT: f32 = 0.3;  // Å
+// Selected atoms without shapes of their own are drawn as shells this much
+// larger than the atoms, to highlight them.
+const SHELL_SCALE: f32 = 1.05;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Measurement {
+    Distance(f32),  // Å
+    Angle(f32),  // Radians
+    Dihedral(f32),  // Radians, from -τ/2 to τ/2.
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Measurement::Distance(d) => write!(f, "Distance {:.3} Å", d),
+            Measurement::Angle(a) => write!(f, "Angle {:.1}°", a.to_degrees()),
+            Measurement::Dihedral(a) => write!(f, "Dihedral {:.1}°", a.to_degrees()),
+        }
+    }
+}
+
+impl Measurement {
+    fn label(&self) -> String {
+        // Shorter than `Display`, for drawing in the scene. Distances are in Å.
+        match self {
+            Measurement::Distance(d) => format!("{:.2}", d),
+            Measurement::Angle(a) | Measurement::Dihedral(a) => format!("{:.1}°", a.to_degrees()),
+        }
+    }
+}
+
+pub fn distance(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    let d = sub_arr(a, b);
+    dot3(&d, &d).sqrt()
+}
+
+pub fn angle(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> f32 {
+    // The angle at b.
+    let (u, v) = (normalize(&sub_arr(a, b)), normalize(&sub_arr(c, b)));
+    dot3(&u, &v).max(-1.).min(1.).acos()
+}
+
+pub fn dihedral(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3], d: &[f32; 3]) -> f32 {
+    // The torsion angle about the b-c axis, between the a-b-c and b-c-d planes.
+    // Positive is clockwise looking from b to c, per IUPAC.
+    let (b1, b2, b3) = (sub_arr(b, a), sub_arr(c, b), sub_arr(d, c));
+    let (n1, n2) = (cross(&b1, &b2), cross(&b2, &b3));
+    let y = dot3(&b2, &b2).sqrt() * dot3(&b1, &n2);
+    y.atan2(dot3(&n1, &n2))
+}
+
+pub fn measure(positions: &[[f32; 3]], atoms: &[usize]) -> Option<Measurement> {
+    // Measure between 2, 3, or 4 atoms, by index into `positions`.
+    let p = |i: usize| &positions[atoms[i]];
+    match atoms.len() {
+        2 => Some(Measurement::Distance(distance(p(0), p(1)))),
+        3 => Some(Measurement::Angle(angle(p(0), p(1), p(2)))),
+        4 => Some(Measurement::Dihedral(dihedral(p(0), p(1), p(2), p(3)))),
+        _ => None,
+    }
+}
+
+pub fn scene_measurement(scene: &Scene) -> Option<Measurement> {
+    let molecule = scene.molecule.as_ref()?;
+    measure(&scenes::atom_positions(molecule), &scene.selection)
+}
+
+fn measurement_id(molecule: &Molecule) -> u32 {
+    // Measurement shapes' ids follow atoms', bonds', and batches'.
+    scenes::batch_id(molecule) + 2
+}
+
+fn label_shape(positions: &[[f32; 3]], atoms: &[usize], measurement: Measurement) -> Shape {
+    // A label at the measured atoms' centroid, raised so it clears the lines
+    // there, and facing the camera.
+    let mut center = [0., 0., 0.];
+    for &i in atoms {
+        center = add_arr(&center, &positions[i]);
+    }
+    let center = mul_arr(&center, 1. / atoms.len() as f32);
+
+    let mesh = shape_maker::segment_text(&measurement.label(), LABEL_HEIGHT, MEASURE_COLOR);
+    let lift = transforms::translate(&[0., LABEL_HEIGHT / 2., 0.]);
+    let mut shape = Shape::new(shape_maker::transform_mesh(&mesh, &lift), center, [0., 0., 0.]);
+    shape.billboard = true;
+    shape
+}
+
+pub fn add_measurement_shapes(scene: &mut Scene) {
+    // Replace dashed lines along the measured path, and its label, for the
+    // current selection.
+    // Selected atoms with no shapes of their own to highlight, eg in batched
+    // molecules, or bonded atoms in licorice, get shells with their atoms' ids.
+    let (first_id, marks, shells, has_shape) = match scene.molecule {
+        Some(ref molecule) => {
+            let positions = scenes::atom_positions(molecule);
+            let mut marks = Vec::new();
+            if let Some(measurement) = scene_measurement(scene) {
+                for pair in scene.selection.windows(2) {
+                    let (start, end) = (positions[pair[0]], positions[pair[1]]);
+                    let num_dashes = (distance(&start, &end) / (2. * DASH_LENGTH)).round() as u32;
+                    let mesh = shape_maker::dashed_cylinder(
+                        start, end, DASH_RADIUS, 6, num_dashes, (MEASURE_COLOR, MEASURE_COLOR)
+                    );
+                    marks.push(Shape::new(mesh, [0., 0., 0.], [0., 0., 0.]));
+                }
+                marks.push(label_shape(&positions, &scene.selection, measurement));
+            }
+
+            let has_shape = if scenes::batched(molecule) {
+                vec![false; molecule.atoms.len()]
+            } else {
+                scenes::atom_spheres(molecule, scene.representation)
+            };
+            let shells: Vec<(u32, Shape)> = scene.selection.iter().filter(|&&i| !has_shape[i])
+                .map(|&i| {
+                    let element = &molecule.atoms[i].element;
+                    let radius = scenes::atom_radius(element, scene.representation) * SHELL_SCALE;
+                    (i as u32, scenes::atom_sphere(element, positions[i], radius))
+                }).collect();
+            (measurement_id(molecule), marks, shells, has_shape)
+        }
+        None => return,
+    };
+
+    // Other shapes with atom ids, and no atoms drawn there, are old shells.
+    scene.shapes.retain(|id, _| {
+        *id < first_id && has_shape.get(*id as usize).cloned().unwrap_or(true)
+    });
+    for (i, mark) in marks.into_iter().enumerate() {
+        scene.shapes.insert(first_id + i as u32, mark);
+    }
+    scene.shapes.extend(shells);
+}
+
+pub fn click(scene: &mut Scene, atom: Option<usize>, shift: bool) {
+    // Clicking an atom selects only it; clicking empty space clears the
+    // selection. Shift-clicking toggles an atom, keeping the rest.
+    match (atom, shift) {
+        (Some(i), true) => {
+            if scene.selection.contains(&i) {
+                scene.selection.retain(|s| *s != i);
+            } else {
+                scene.selection.push(i);
+            }
+        }
+        (Some(i), false) => scene.selection = vec![i],
+        (None, true) => (),
+        (None, false) => scene.selection.clear(),
+    }
+    add_measurement_shapes(scene);
+}
+
+pub fn box_select(scene: &mut Scene, from: [f32; 2], to: [f32; 2], dimensions: [u32; 2]) {
+    // Add atoms whose centers are inside a rectangle on the screen, in pixels
+    // from the top left, to the selection.
+    let inside = match scene.molecule {
+        Some(ref molecule) => {
+            let PV = dot(transforms::proj(&scene.cam),
+                         transforms::view(&scene.cam.position, &scene.cam.orientation));
+            let (x0, x1) = (from[0].min(to[0]), from[0].max(to[0]));
+            let (y0, y1) = (from[1].min(to[1]), from[1].max(to[1]));
+
+            scenes::atom_positions(molecule).iter().enumerate().filter(|(_, p)| {
+                let clip = dot_v(&PV, [p[0], p[1], p[2], 1.]);
+                if clip[3] <= 0. {
+                    return false;  // Behind the camera.
+                }
+                // Vulkan's y is down, like pixels'.
+                let x = (clip[0] / clip[3] + 1.) / 2. * dimensions[0] as f32;
+                let y = (clip[1] / clip[3] + 1.) / 2. * dimensions[1] as f32;
+                x >= x0 && x <= x1 && y >= y0 && y <= y1
+            }).map(|(i, _)| i).collect::<Vec<_>>()
+        }
+        None => return,
+    };
+
+    for i in inside {
+        if !scene.selection.contains(&i) {
+            scene.selection.push(i);
+        }
+    }
+    add_measurement_shapes(scene);
+}
+
+pub fn status(scene: &Scene) -> Option<String> {
+    // A description of the selection, eg for a title bar: the atom for single
+    // selections, and the measurement for 2 to 4.
+    let molecule = scene.molecule.as_ref()?;
+    match scene.selection.len() {
+        0 => None,
+        1 => Some(pick::describe(molecule, scene.selection[0])),
+        n => {
+            let atoms: Vec<String> = scene.selection.iter().map(|i| pick::describe(molecule, *i)).collect();
+            Some(match scene_measurement(scene) {
+                Some(m) => format!("{}: {}", atoms.join(" - "), m),
+                None => format!("{} atoms selected", n),
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+    use molecule::{Atom, Bond};
+    use renderer::scene_uniforms;
+    use types::{BondOrder, Representation};
+
+    #[test]
+    fn measurements() {
+        // Water: O-H 0.9572 Å, H-O-H 104.52°.
+        let θ = 104.52f32.to_radians();
+        let positions = [
+            [0., 0., 0.],
+            [0.9572, 0., 0.],
+            [0.9572 * θ.cos(), 0.9572 * θ.sin(), 0.],
+        ];
+        assert_eq!(measure(&positions, &[0, 1]), Some(Measurement::Distance(0.9572)));
+        match measure(&positions, &[1, 0, 2]) {
+            Some(Measurement::Angle(a)) => assert!((a - θ).abs() < 1e-5),
+            other => panic!("{:?}", other),
+        }
+        assert_eq!(measure(&positions, &[0]), None);
+
+        // Butane-like torsions: anti, and gauche either way.
+        let (a, b, c) = ([1., 1., 0.], [0., 0., 0.], [0., 0., 1.]);
+        assert!((dihedral(&a, &b, &c, &[-1., -1., 1.]).abs() - PI).abs() < 1e-5);
+        assert!((dihedral(&a, &b, &c, &[0., 1., 1.]) - PI / 4.).abs() < 1e-5);
+        assert!((dihedral(&a, &b, &c, &[1., 0., 1.]) + PI / 4.).abs() < 1e-5);
+
+        assert_eq!(format!("{}", Measurement::Angle(PI / 2.)), "Angle 90.0°");
+    }
+
+    #[test]
+    fn select() {
+        let molecule = Molecule::new("H2O", vec![
+            Atom::new("O", [0., 0., 0.]),
+            Atom::new("H", [0.96, 0., 0.]),
+            Atom::new("H", [-0.24, 0.93, 0.]),
+        ], vec![Bond::new(0, 1, BondOrder::Single), Bond::new(0, 2, BondOrder::Single)]);
+        let mut scene = scenes::from_molecule(1., &molecule, Representation::BallAndStick);
+        let num_shapes = scene.shapes.len();
+
+        click(&mut scene, Some(1), false);
+        click(&mut scene, Some(0), true);
+        assert_eq!(scene.selection, vec![1, 0]);
+        assert_eq!(scene.shapes.len(), num_shapes + 2);  // A dashed line, and its label.
+        assert!(scene.shapes[&(measurement_id(&molecule) + 1)].billboard);
+        assert!(status(&scene).unwrap().ends_with("Distance 0.960 Å"));
+
+        // Selected atoms are highlighted, and other shapes aren't.
+        let uniforms = scene_uniforms(&scene, &scene.cam);
+        assert_eq!(uniforms[&0].highlight, 1.);
+        assert_eq!(uniforms[&2].highlight, 0.);
+        assert_eq!(uniforms[&3].highlight, 0.);  // A bond.
+
+        click(&mut scene, Some(2), true);
+        assert!(status(&scene).unwrap().contains("Angle"));
+        assert_eq!(scene.shapes.len(), num_shapes + 3);
+        click(&mut scene, Some(1), true);
+        assert_eq!(scene.selection, vec![0, 2]);
+
+        // Measurement lines survive a change of representation.
+        scenes::set_representation(&mut scene, Representation::Licorice);
+        assert!(scene.shapes.contains_key(&(measurement_id(&molecule))));
+
+        // Bonded atoms in licorice have no shapes, so they get shells.
+        assert_eq!(scene_uniforms(&scene, &scene.cam)[&0].highlight, 1.);
+        assert!(!scene.shapes.contains_key(&1));
+
+        click(&mut scene, None, false);
+        assert!(scene.selection.is_empty());
+        assert!(!scene.shapes.contains_key(&(measurement_id(&molecule))));
+        assert!(!scene.shapes.contains_key(&0));
+
+        // A box around the whole view selects everything.
+        box_select(&mut scene, [0., 0.], [100., 100.], [100, 100]);
+        assert_eq!(scene.selection.len(), 3);
+        scene.selection.clear();
+        box_select(&mut scene, [0., 0.], [10., 10.], [100, 100]);
+        assert!(scene.selection.is_empty());
+    }
+}
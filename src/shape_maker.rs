@@ -1,15 +1,20 @@
 use std::collections::HashMap;
-//use std::f32::consts::PI;
+use std::f32::consts::PI;
 
 use ndarray::prelude::*;
 
-use types::{Vertex, Mesh, Normal};
+use ops::{cross, dot_v, inverse, normalize, sub_arr, transpose};
+use transforms;
+use types::{BondOrder, Vertex, Mesh, Normal};
 
 fn add(left: &Vec<u32>, val: u32) -> Vec<u32> {
     left.iter().map(|item| item + val).collect()
 }
 
-//const τ: f32 = 2. * PI;
+const τ: f32 = 2. * PI;
+// Lathed shapes are smooth around and along their axis, but keep sharp edges
+// where faces meet at more than this angle; eg square wireframe sticks.
+const LATHE_CREASE: f32 = τ / 8.;
 
 // We'll define y as vertical, and z as forward/back.  All shapes are given
 // four coordinates. Leave
@@ -30,33 +35,43 @@ pub fn make_normals(vertices: &HashMap<u32, Vertex>, faces: &Vec<Array1<u32>>) -
 }
 
 
-pub fn _combine_meshes(mut base: Mesh, meshes: Vec<(Mesh, [f32; 3])>) -> Mesh{
-    // The array in the meshes tuple is position offset for that shape.
-    let mut id_addition = base.vertices.len() as u32;
-    for (mesh, offset) in &meshes {
-        for (id, vertex) in &mesh.vertices {
-            // For the roof, modify the ids to be unique.
-            base.vertices.insert(
-                id + id_addition,
-                Vertex::new(vertex.position[0] + offset[0], vertex.position[1] + offset[1],
-                            vertex.position[2] + offset[2]
-                )
-            );
-        }
-
-        for face in &mesh.faces_vert {
-            base.faces_vert.push(add(face, id_addition));
-        }
-
-        for normal in &mesh.normals {  // todo rotate normals!
-            base.normals.push(normal.clone());
-        }
-
-        id_addition += mesh.vertices.len() as u32;
+pub fn transform_mesh(mesh: &Mesh, model: &[[f32; 4]; 4]) -> Mesh {
+    // A copy of a mesh with a model matrix, eg from `transforms::model`, applied.
+    // Normals use the inverse transpose, so they stay perpendicular to their
+    // faces under non-uniform scaling. The shaders normalize them.
+    let normal_matrix = inverse(*model).map(transpose).unwrap_or(*model);
+    let point = |p: [f32; 3]| {
+        let v = dot_v(model, [p[0], p[1], p[2], 1.]);
+        Vertex::new(v[0], v[1], v[2])
+    };
+    let normal = |n: &Normal| {
+        let v = dot_v(&normal_matrix, [n.normal[0], n.normal[1], n.normal[2], 0.]);
+        Normal::new(v[0], v[1], v[2])
+    };
+
+    Mesh {
+        vertices: mesh.vertices.iter().map(|(id, v)| (*id, point(v.position))).collect(),
+        faces_vert: mesh.faces_vert.clone(),
+        face_colors: mesh.face_colors.clone(),
+        normals: mesh.normals.iter().map(&normal).collect(),
+        vertex_normals: mesh.vertex_normals.iter().map(|face| face.iter().map(&normal).collect()).collect(),
+        tris: mesh.tris.clone(),
     }
+}
 
-    base.make_tris();
-    base
+pub fn combine_meshes(meshes: &[(&Mesh, [[f32; 4]; 4])]) -> Mesh {
+    // Merge meshes, each with its own model matrix, into one; eg to draw many
+    // atoms with a single draw call. Each mesh's ids are offset past the
+    // previous one's highest, so they needn't be dense.
+    let mut result = Mesh::new(HashMap::new(), Vec::new(), Vec::new(), Vec::new());
+    let (mut id_offset, mut index_offset) = (0, 0);
+
+    for (mesh, model) in meshes {
+        append_at(&mut result, &transform_mesh(mesh, model), id_offset, index_offset);
+        id_offset += mesh.vertices.keys().max().map_or(0, |id| id + 1);
+        index_offset += mesh.num_face_verts();
+    }
+    result
 }
 
 pub fn box_(lens: (f32, f32, f32)) -> Mesh {
@@ -168,7 +183,6 @@ pub fn _rect_pyramid(lens: (f32, f32, f32)) -> Mesh {
 }
 
 pub fn _house(lens: (f32, f32, f32)) -> Mesh {
-    // We'll modify base in-place, then return it.
     let base = box_(lens);
 
     let roof = _rect_pyramid(
@@ -177,7 +191,10 @@ pub fn _house(lens: (f32, f32, f32)) -> Mesh {
         (lens.0 * 1.2, lens.1 / 3., lens.2 * 1.2),
     );
 
-    _combine_meshes(base, vec![(roof, [0., lens.1 / 2., 0.])])
+    combine_meshes(&[
+        (&base, transforms::translate(&[0., 0., 0.])),
+        (&roof, transforms::translate(&[0., lens.1 / 2., 0.])),
+    ])
 }
 
 pub fn cube(side_len: f32) -> Mesh {
@@ -186,17 +203,721 @@ pub fn cube(side_len: f32) -> Mesh {
     box_((side_len, side_len, side_len))
 }
 
-fn avg_normals(normals: Vec<Normal>) -> Normal {
+fn face_centroid_normal(vertices: &HashMap<u32, Vertex>, face: &Vec<u32>) -> Normal {
+    // For shapes centered on the origin, such as spheres, the outward normal of a
+    // face points along the face's centroid.
+    let mut sum = [0., 0., 0.];
+    for id in face {
+        let posit = vertices[id].position;
+        sum = [sum[0] + posit[0], sum[1] + posit[1], sum[2] + posit[2]];
+    }
+    let n = normalize(&sum);
+    Normal::new(n[0], n[1], n[2])
+}
+
+fn radial_normals(mesh: &Mesh) -> Vec<Vec<Normal>> {
+    // Smooth normals for spheres centered on the origin: each vertex's normal is
+    // its normalized position.
+    mesh.faces_vert.iter().map(|face| face.iter().map(|id| {
+        let n = normalize(&mesh.vertices[id].position);
+        Normal::new(n[0], n[1], n[2])
+    }).collect()).collect()
+}
+
+pub fn icosphere(radius: f32, subdivisions: u32, color: [f32; 4]) -> Mesh {
+    // Make a sphere by repeatedly subdividing the faces of an icosahedron, and
+    // projecting the new vertices onto the sphere. Each subdivision quadruples
+    // the face count: 20 * 4^subdivisions triangles.
+    // Vertices are shared between adjacent faces, so each vertex's smooth normal
+    // is just its (normalized) position.
+    let t = (1. + 5_f32.sqrt()) / 2.;
+
+    let coords = [
+        [-1., t, 0.], [1., t, 0.], [-1., -t, 0.], [1., -t, 0.],
+        [0., -1., t], [0., 1., t], [0., -1., -t], [0., 1., -t],
+        [t, 0., -1.], [t, 0., 1.], [-t, 0., -1.], [-t, 0., 1.],
+    ];
+
+    // Keep vertices on the unit sphere while subdividing; scale at the end.
+    let mut unit_verts: Vec<[f32; 3]> = coords.iter().map(|c| normalize(c)).collect();
+
+    // Counter-clockwise when viewed from outside.
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edge midpoints are cached so neighboring faces share them.
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut new_faces = Vec::new();
+
+        {
+            let mut midpoint = |a: u32, b: u32| -> u32 {
+                let key = if a < b { (a, b) } else { (b, a) };
+                if let Some(id) = midpoints.get(&key) {
+                    return *id;
+                }
+                let (pa, pb) = (unit_verts[a as usize], unit_verts[b as usize]);
+                unit_verts.push(normalize(&[
+                    (pa[0] + pb[0]) / 2., (pa[1] + pb[1]) / 2., (pa[2] + pb[2]) / 2.
+                ]));
+                let id = unit_verts.len() as u32 - 1;
+                midpoints.insert(key, id);
+                id
+            };
+
+            for face in &faces {
+                let ab = midpoint(face[0], face[1]);
+                let bc = midpoint(face[1], face[2]);
+                let ca = midpoint(face[2], face[0]);
+
+                new_faces.push([face[0], ab, ca]);
+                new_faces.push([face[1], bc, ab]);
+                new_faces.push([face[2], ca, bc]);
+                new_faces.push([ab, bc, ca]);
+            }
+        }
+        faces = new_faces;
+    }
+
+    let mut vertices = HashMap::new();
+    for (id, v) in unit_verts.iter().enumerate() {
+        vertices.insert(id as u32, Vertex::new(v[0] * radius, v[1] * radius, v[2] * radius));
+    }
+
+    let faces_vert: Vec<Vec<u32>> = faces.iter().map(|f| f.to_vec()).collect();
+    let normals = faces_vert.iter().map(|f| face_centroid_normal(&vertices, f)).collect();
+    let face_colors = vec![color; faces_vert.len()];
+
+    let mut result = Mesh::new(vertices, faces_vert, face_colors, normals);
+    result.vertex_normals = radial_normals(&result);
+    result
+}
+
+pub fn uv_sphere(radius: f32, num_lat: u32, num_lon: u32, color: [f32; 4]) -> Mesh {
+    // Make a sphere from latitude bands and longitude slices, like a globe.
+    // The bands touching the poles are triangles; the others are quads.
+    // num_lat must be at least 2, and num_lon at least 3.
+    let num_lat = num_lat.max(2);
+    let num_lon = num_lon.max(3);
+
+    let mut vertices = HashMap::new();
+    // Poles are ids 0 (top) and 1 (bottom); rings follow, top to bottom.
+    vertices.insert(0, Vertex::new(0., radius, 0.));
+    vertices.insert(1, Vertex::new(0., -radius, 0.));
+
+    let ring_id = |lat: u32, lon: u32| 2 + (lat - 1) * num_lon + lon % num_lon;
+
+    for lat in 1..num_lat {
+        let φ = PI * lat as f32 / num_lat as f32;  // Angle from the top pole.
+        for lon in 0..num_lon {
+            let θ = τ * lon as f32 / num_lon as f32;
+            vertices.insert(ring_id(lat, lon), Vertex::new(
+                radius * φ.sin() * θ.cos(),
+                radius * φ.cos(),
+                -radius * φ.sin() * θ.sin(),
+            ));
+        }
+    }
+
+    let mut faces_vert = Vec::new();
+    for lon in 0..num_lon {
+        faces_vert.push(vec![0, ring_id(1, lon), ring_id(1, lon + 1)]);
+    }
+    for lat in 1..num_lat - 1 {
+        for lon in 0..num_lon {
+            faces_vert.push(vec![
+                ring_id(lat, lon), ring_id(lat + 1, lon),
+                ring_id(lat + 1, lon + 1), ring_id(lat, lon + 1),
+            ]);
+        }
+    }
+    for lon in 0..num_lon {
+        faces_vert.push(vec![1, ring_id(num_lat - 1, lon + 1), ring_id(num_lat - 1, lon)]);
+    }
+
+    let normals = faces_vert.iter().map(|f| face_centroid_normal(&vertices, f)).collect();
+    let face_colors = vec![color; faces_vert.len()];
+
+    let mut result = Mesh::new(vertices, faces_vert, face_colors, normals);
+    result.vertex_normals = radial_normals(&result);
+    result
+}
+
+fn perpendicular(dir: [f32; 3]) -> [f32; 3] {
+    // Any unit vector perpendicular to the (unit) direction.
+    let helper = if dir[0].abs() < 0.9 { [1., 0., 0.] } else { [0., 1., 0.] };
+    normalize(&cross(&dir, &helper))
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+fn append(base: &mut Mesh, other: &Mesh) {
+    // Add another mesh's geometry to this one, as-is. Ids are offset past the
+    // highest existing id.
+    let id_offset = base.vertices.keys().max().map_or(0, |id| id + 1);
+    let index_offset = base.num_face_verts();
+    append_at(base, other, id_offset, index_offset);
+}
+
+fn append_at(base: &mut Mesh, other: &Mesh, id_offset: u32, index_offset: u32) {
+    // `append`, with the offsets given, for callers that track them; finding
+    // them is slow for large meshes. `index_offset` is the base's number of
+    // face vertices.
+
+    // If either mesh is smooth, give the flat one's face vertices their faces'
+    // normals, so the result's vertex normals cover every face.
+    if !base.vertex_normals.is_empty() || !other.vertex_normals.is_empty() {
+        let corner_normals = |mesh: &Mesh| -> Vec<Vec<Normal>> {
+            if !mesh.vertex_normals.is_empty() {
+                return mesh.vertex_normals.clone();
+            }
+            mesh.faces_vert.iter().zip(&mesh.normals).map(|(face, n)| vec![*n; face.len()]).collect()
+        };
+        if base.vertex_normals.is_empty() {
+            base.vertex_normals = corner_normals(base);
+        }
+        base.vertex_normals.extend(corner_normals(other));
+    }
+    base.normals.extend_from_slice(&other.normals);
+
+    for (id, vertex) in &other.vertices {
+        base.vertices.insert(id + id_offset, *vertex);
+    }
+    for face in &other.faces_vert {
+        base.faces_vert.push(add(face, id_offset));
+    }
+    base.face_colors.extend_from_slice(&other.face_colors);
+
+    // Both meshes are already triangulated; their index buffers just follow
+    // each other.
+    base.tris.extend(other.tris.iter().map(|t| t + index_offset));
+}
+
+fn lathe(start: [f32; 3], end: [f32; 3], profile: &[(f32, f32)], segments: u32,
+         color: [f32; 4]) -> Mesh {
+    // Revolve a profile around the axis from `start` to `end`. Profile points are
+    // (distance along the axis from `start`, radius), in order. A radius of 0
+    // makes a single tip vertex, eg a capsule's end.
+    let segments = segments.max(3);
+    let dir = normalize(&sub_arr(&end, &start));
+    let u = perpendicular(dir);
+    let v = cross(&dir, &u);
+
+    let mut vertices = HashMap::new();
+    let mut rings: Vec<Vec<u32>> = Vec::new();
+
+    for (axial, radius) in profile {
+        let center = [start[0] + dir[0] * axial, start[1] + dir[1] * axial,
+                      start[2] + dir[2] * axial];
+        let mut ring = Vec::new();
+
+        if *radius == 0. {
+            let id = vertices.len() as u32;
+            vertices.insert(id, Vertex::new(center[0], center[1], center[2]));
+            ring.push(id);
+        } else {
+            for i in 0..segments {
+                let θ = τ * i as f32 / segments as f32;
+                let (c, s) = (θ.cos() * radius, θ.sin() * radius);
+                let id = vertices.len() as u32;
+                vertices.insert(id, Vertex::new(
+                    center[0] + u[0] * c + v[0] * s,
+                    center[1] + u[1] * c + v[1] * s,
+                    center[2] + u[2] * c + v[2] * s,
+                ));
+                ring.push(id);
+            }
+        }
+        rings.push(ring);
+    }
+
+    let mut faces_vert = Vec::new();
+    for pair in rings.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        for i in 0..segments as usize {
+            let next = (i + 1) % segments as usize;
+            // Counter-clockwise when viewed from outside.
+            faces_vert.push(match (a.len(), b.len()) {
+                (1, 1) => continue,
+                (1, _) => vec![a[0], b[next], b[i]],
+                (_, 1) => vec![a[i], a[next], b[0]],
+                _ => vec![a[i], a[next], b[next], b[i]],
+            });
+        }
+    }
+
+    // Flat normals from the face winding.
+    let normals = faces_vert.iter().map(|face| {
+        let n = if face.len() == 3 {
+            vertices[&face[1]].subtract(&vertices[&face[0]])
+                .cross(&vertices[&face[2]].subtract(&vertices[&face[0]])).normal
+        } else {
+            // The diagonals of a quad give its normal, even if it's slightly
+            // non-planar.
+            vertices[&face[2]].subtract(&vertices[&face[0]])
+                .cross(&vertices[&face[3]].subtract(&vertices[&face[1]])).normal
+        };
+        let n = normalize(&n);
+        Normal::new(n[0], n[1], n[2])
+    }).collect();
+
+    let face_colors = vec![color; faces_vert.len()];
+
+    let mut result = Mesh::new(vertices, faces_vert, face_colors, normals);
+    smooth_normals(&mut result, LATHE_CREASE);
+    result
+}
+
+pub fn cylinder(start: [f32; 3], end: [f32; 3], radius: f32, segments: u32,
+                color: [f32; 4]) -> Mesh {
+    // Make an open-ended cylinder running from `start` to `end`; eg a bond stick.
+    // Unlike most shapes here, the mesh isn't centered on the origin; pass
+    // endpoints relative to where the shape will be positioned.
+    let len = Vertex::new(end[0], end[1], end[2]).subtract(&Vertex::new(start[0], start[1], start[2]));
+    let len = len.position.iter().map(|c| c.powi(2)).sum::<f32>().sqrt();
+
+    lathe(start, end, &[(0., radius), (len, radius)], segments, color)
+}
+
+pub fn capsule(start: [f32; 3], end: [f32; 3], radius: f32, segments: u32,
+               color: [f32; 4]) -> Mesh {
+    // A cylinder with hemispherical caps centered on `start` and `end`; used for
+    // licorice sticks, which have rounded ends.
+    let len = Vertex::new(end[0], end[1], end[2]).subtract(&Vertex::new(start[0], start[1], start[2]));
+    let len = len.position.iter().map(|c| c.powi(2)).sum::<f32>().sqrt();
+
+    let cap_rings = (segments / 4).max(2);
+    let mut profile = Vec::new();
+    // The rings where the caps meet the cylinder, and the tips, are set exactly;
+    // eg cos(τ/4) isn't quite 0 in f32, and tips need a radius of exactly 0.
+    for i in 0..cap_rings {
+        let φ = τ / 4. * i as f32 / cap_rings as f32;
+        profile.push((-radius * φ.cos(), radius * φ.sin()));
+    }
+    profile.push((0., radius));
+    profile.push((len, radius));
+    for i in 1..cap_rings {
+        let φ = τ / 4. * i as f32 / cap_rings as f32;
+        profile.push((len + radius * φ.sin(), radius * φ.cos()));
+    }
+    profile.push((len + radius, 0.));
+
+    lathe(start, end, &profile, segments, color)
+}
+
+pub fn split_cylinder(start: [f32; 3], end: [f32; 3], radius: f32, segments: u32,
+                      colors: ([f32; 4], [f32; 4])) -> Mesh {
+    // A cylinder whose halves are colored separately; eg by the atoms at each end
+    // of a bond.
+    let mid = lerp(start, end, 0.5);
+    let mut result = cylinder(start, mid, radius, segments, colors.0);
+    append(&mut result, &cylinder(mid, end, radius, segments, colors.1));
+    result
+}
+
+pub fn split_capsule(start: [f32; 3], end: [f32; 3], radius: f32, segments: u32,
+                     colors: ([f32; 4], [f32; 4])) -> Mesh {
+    // Like split_cylinder, with rounded ends.
+    let mid = lerp(start, end, 0.5);
+    let mut result = capsule(start, mid, radius, segments, colors.0);
+    append(&mut result, &capsule(mid, end, radius, segments, colors.1));
+    result
+}
+
+pub fn dashed_cylinder(start: [f32; 3], end: [f32; 3], radius: f32, segments: u32,
+                       num_dashes: u32, colors: ([f32; 4], [f32; 4])) -> Mesh {
+    // Dashes and gaps are the same length, with a dash at each end. Each dash is
+    // colored by the end it's closest to.
+    let num_dashes = num_dashes.max(1);
+    let pieces = (2 * num_dashes - 1) as f32;
+
+    let mut result = Mesh::new(HashMap::new(), Vec::new(), Vec::new(), Vec::new());
+    for i in 0..num_dashes {
+        let (t0, t1) = (2. * i as f32 / pieces, (2. * i as f32 + 1.) / pieces);
+        let color = if t0 + t1 < 1. { colors.0 } else { colors.1 };
+        append(&mut result, &cylinder(lerp(start, end, t0), lerp(start, end, t1),
+                                      radius, segments, color));
+    }
+    result
+}
+
+fn segments(c: char) -> Option<u8> {
+    // Lit segments of a seven-segment glyph: bits 0 through 6 are the top,
+    // upper right, lower right, bottom, lower left, upper left, and middle.
+    let bits = match c {
+        '0' => 0b0111111,
+        '1' => 0b0000110,
+        '2' => 0b1011011,
+        '3' => 0b1001111,
+        '4' => 0b1100110,
+        '5' => 0b1101101,
+        '6' => 0b1111101,
+        '7' => 0b0000111,
+        '8' => 0b1111111,
+        '9' => 0b1101111,
+        '-' => 0b1000000,
+        '°' => 0b1100011,
+        _ => return None,
+    };
+    Some(bits)
+}
+
+pub fn segment_text(text: &str, height: f32, color: [f32; 4]) -> Mesh {
+    // Text drawn as seven-segment glyphs of thin boxes, in the xy plane, facing
+    // -z, centered on x, with its baseline at y = 0; eg for labels. Handles
+    // digits, '-', '.', and '°'; other characters are skipped.
+    let (width, thickness) = (height / 2., height / 10.);
+    let horizontal = box_((width + thickness, thickness, thickness));
+    let vertical = box_((thickness, height / 2. + thickness, thickness));
+    let dot = box_((thickness, thickness, thickness));
+
+    let mut pieces = Vec::new();
+    let mut x = 0.;
+    for c in text.chars() {
+        if c == '.' {
+            pieces.push((&dot, [x + thickness / 2., 0.]));
+            x += 3. * thickness;
+            continue;
+        }
+        let bits = match segments(c) {
+            Some(bits) => bits,
+            None => continue,
+        };
+        let places = [
+            (&horizontal, [width / 2., height]),
+            (&vertical, [width, 3. * height / 4.]),
+            (&vertical, [width, height / 4.]),
+            (&horizontal, [width / 2., 0.]),
+            (&vertical, [0., height / 4.]),
+            (&vertical, [0., 3. * height / 4.]),
+            (&horizontal, [width / 2., height / 2.]),
+        ];
+        for (i, (mesh, place)) in places.iter().enumerate() {
+            if bits & (1 << i) != 0 {
+                pieces.push((*mesh, [x + place[0], place[1]]));
+            }
+        }
+        x += width + 3. * thickness;
+    }
+
+    // Center on x, without the trailing gap.
+    let offset = (x - 2. * thickness) / 2.;
+    let pieces: Vec<_> = pieces.into_iter()
+        .map(|(mesh, p)| (mesh, transforms::translate(&[p[0] - offset, p[1], 0.])))
+        .collect();
+    let mut result = combine_meshes(&pieces);
+    for face_color in &mut result.face_colors {
+        *face_color = color;
+    }
+    result
+}
+
+pub fn bond_sticks(start: [f32; 3], end: [f32; 3], order: BondOrder, radius: f32, segments: u32,
+                   colors: ([f32; 4], [f32; 4]), side: [f32; 3]) -> Mesh {
+    // Lay out the sticks for a bond, each half-colored by the atom at its end:
+    // one stick for single bonds, 2 or 3 parallel, thinner sticks for double and
+    // triple bonds, and a stick with a dashed partner for aromatic bonds.
+    // `side` points towards where offset sticks should go, eg at a neighboring
+    // atom, so they lie in the plane of the molecule. It's only used for its
+    // component perpendicular to the bond.
+    let dir = normalize(&sub_arr(&end, &start));
+    let along = side[0] * dir[0] + side[1] * dir[1] + side[2] * dir[2];
+    let perp = [side[0] - dir[0] * along, side[1] - dir[1] * along, side[2] - dir[2] * along];
+
+    let perp_len = (perp[0].powi(2) + perp[1].powi(2) + perp[2].powi(2)).sqrt();
+    let offset_dir = if perp_len > 1e-4 { normalize(&perp) } else { perpendicular(dir) };
+
+    let thin = radius * 0.6;
+    let shifted = |dist: f32| {
+        let o = [offset_dir[0] * dist, offset_dir[1] * dist, offset_dir[2] * dist];
+        ([start[0] + o[0], start[1] + o[1], start[2] + o[2]],
+         [end[0] + o[0], end[1] + o[1], end[2] + o[2]])
+    };
+
+    match order {
+        BondOrder::Single => split_cylinder(start, end, radius, segments, colors),
+        BondOrder::Double => {
+            let (a, b) = (shifted(-1.5 * thin), shifted(1.5 * thin));
+            let mut result = split_cylinder(a.0, a.1, thin, segments, colors);
+            append(&mut result, &split_cylinder(b.0, b.1, thin, segments, colors));
+            result
+        },
+        BondOrder::Triple => {
+            let (a, b) = (shifted(-2.5 * thin), shifted(2.5 * thin));
+            let mut result = split_cylinder(start, end, thin, segments, colors);
+            append(&mut result, &split_cylinder(a.0, a.1, thin, segments, colors));
+            append(&mut result, &split_cylinder(b.0, b.1, thin, segments, colors));
+            result
+        },
+        BondOrder::Aromatic => {
+            // The dashed stick is shortened, as it's drawn inside the ring.
+            let (a, b) = (shifted(-thin), shifted(2. * thin));
+            let mut result = split_cylinder(a.0, a.1, thin, segments, colors);
+            append(&mut result, &dashed_cylinder(
+                lerp(b.0, b.1, 0.15), lerp(b.0, b.1, 0.85), thin * 0.8, segments, 3, colors
+            ));
+            result
+        },
+    }
+}
+
+pub fn avg_normals(normals: &[Normal]) -> Normal {
+    // Normalized, so it's unit length even when the normals aren't parallel.
     let x = normals.iter().fold(0., |acc, norm| acc + norm.normal[0]);
     let y = normals.iter().fold(0., |acc, norm| acc + norm.normal[1]);
     let z = normals.iter().fold(0., |acc, norm| acc + norm.normal[2]);
 
-    let len = normals.len() as f32;
-    Normal::new(x/len , y/len, z/len)
+    let n = normalize(&[x, y, z]);
+    Normal::new(n[0], n[1], n[2])
+}
+
+pub fn smooth_normals(mesh: &mut Mesh, crease_angle: f32) {
+    // Give each face vertex the average normal of the faces around it, so curved
+    // surfaces shade smoothly. Faces whose normals differ from a face's by more
+    // than `crease_angle` aren't included in its average, leaving a sharp edge;
+    // eg 0 keeps all faces flat, and τ/2 smooths everything.
+    // Only faces that share vertex ids are averaged together.
+    let face_normals: Vec<[f32; 3]> = mesh.normals.iter().map(|n| normalize(&n.normal)).collect();
+
+    let mut vertex_faces: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, face) in mesh.faces_vert.iter().enumerate() {
+        for id in face {
+            vertex_faces.entry(*id).or_insert_with(Vec::new).push(i);
+        }
+    }
+
+    // Allow some rounding error, so faces exactly at the crease angle are smoothed.
+    let min_cos = crease_angle.cos() - 1e-5;
+    let dot = |a: [f32; 3], b: [f32; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+
+    mesh.vertex_normals = mesh.faces_vert.iter().enumerate().map(|(i, face)| {
+        face.iter().map(|id| {
+            let adjacent: Vec<Normal> = vertex_faces[id].iter()
+                .filter(|j| dot(face_normals[**j], face_normals[i]) >= min_cos)
+                .map(|j| {
+                    let n = face_normals[*j];
+                    Normal::new(n[0], n[1], n[2])
+                })
+                .collect();
+            avg_normals(&adjacent)
+        }).collect()
+    }).collect();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ops::dot3;
+
+    #[test]
+    fn icosphere_counts_and_normals() {
+        let mesh = icosphere(2., 2, [1., 1., 1., 1.]);
+        // 20 * 4^2 faces; V = F / 2 + 2 for a closed triangle mesh.
+        assert_eq!(mesh.faces_vert.len(), 320);
+        assert_eq!(mesh.vertices.len(), 162);
+        assert_eq!(mesh.face_colors.len(), mesh.faces_vert.len());
+
+        for v in mesh.vertices.values() {
+            assert!((dot3(&v.position, &v.position).sqrt() - 2.).abs() < 1e-5);
+        }
 
-}
\ No newline at end of file
+        // Normals point outward, and agree with the face winding.
+        for (face, normal) in mesh.faces_vert.iter().zip(&mesh.normals) {
+            let a = mesh.vertices[&face[0]];
+            let winding = mesh.vertices[&face[1]].subtract(&a)
+                .cross(&mesh.vertices[&face[2]].subtract(&a));
+            assert!(dot3(&normal.normal, &a.position) > 0.);
+            assert!(dot3(&normal.normal, &winding.normal) > 0.);
+        }
+    }
+
+    fn dist_to_axis(p: [f32; 3], start: [f32; 3], dir: [f32; 3]) -> (f32, f32) {
+        // (distance along the axis from start, distance from the axis)
+        let d = [p[0] - start[0], p[1] - start[1], p[2] - start[2]];
+        let along = dot3(&d, &dir);
+        let perp = [d[0] - dir[0] * along, d[1] - dir[1] * along, d[2] - dir[2] * along];
+        (along, dot3(&perp, &perp).sqrt())
+    }
+
+    #[test]
+    fn cylinder_between_points() {
+        let (start, end) = ([1., 2., 3.], [1., 2., 7.]);
+        let mesh = cylinder(start, end, 0.5, 8, [1., 1., 1., 1.]);
+        assert_eq!(mesh.vertices.len(), 16);
+        assert_eq!(mesh.faces_vert.len(), 8);
+
+        for v in mesh.vertices.values() {
+            let (along, radius) = dist_to_axis(v.position, start, [0., 0., 1.]);
+            assert!(along.abs() < 1e-5 || (along - 4.).abs() < 1e-5);
+            assert!((radius - 0.5).abs() < 1e-5);
+        }
+
+        // Side normals are perpendicular to the axis, and point outward.
+        for (face, normal) in mesh.faces_vert.iter().zip(&mesh.normals) {
+            let offset = mesh.vertices[&face[0]].subtract(&Vertex::new(1., 2., 3.)).position;
+            assert!(normal.normal[2].abs() < 1e-5);
+            assert!(dot3(&normal.normal, &offset) > 0.);
+        }
+    }
+
+    #[test]
+    fn capsule_caps() {
+        let (start, end) = ([0., 0., 0.], [0., 3., 0.]);
+        let mesh = capsule(start, end, 0.5, 12, [1., 1., 1., 1.]);
+
+        let mut max_along: f32 = 0.;
+        for v in mesh.vertices.values() {
+            let (along, _) = dist_to_axis(v.position, start, [0., 1., 0.]);
+            max_along = max_along.max(along.abs());
+            // Every vertex is within the radius of the axis segment.
+            let nearest = [0., along.max(0.).min(3.), 0.];
+            let d = v.subtract(&Vertex::new(nearest[0], nearest[1], nearest[2])).position;
+            assert!((dot3(&d, &d).sqrt() - 0.5).abs() < 1e-5);
+        }
+        assert!((max_along - 3.5).abs() < 1e-5);
+
+        // Each cap ends in a single tip vertex, rather than a ring of coincident ones.
+        let tips = mesh.vertices.values()
+            .filter(|v| dist_to_axis(v.position, start, [0., 1., 0.]).1 < 1e-6)
+            .count();
+        assert_eq!(tips, 2);
+
+        for (face, normal) in mesh.faces_vert.iter().zip(&mesh.normals) {
+            let p = mesh.vertices[&face[0]].position;
+            let nearest = [0., p[1].max(0.).min(3.), 0.];
+            assert!(dot3(&normal.normal, &sub_arr(&p, &nearest)) > 0.);
+        }
+    }
+
+    #[test]
+    fn bond_layouts() {
+        let white = [1., 1., 1., 1.];
+        let red = [1., 0., 0., 1.];
+        let (start, end) = ([0., 0., 0.], [2., 0., 0.]);
+
+        let single = bond_sticks(start, end, BondOrder::Single, 0.1, 8, (white, red), [0., 1., 0.]);
+        let double = bond_sticks(start, end, BondOrder::Double, 0.1, 8, (white, red), [0., 1., 0.]);
+        let triple = bond_sticks(start, end, BondOrder::Triple, 0.1, 8, (white, red), [0., 1., 0.]);
+        assert_eq!(single.faces_vert.len(), 16);
+        assert_eq!(double.faces_vert.len(), 32);
+        assert_eq!(triple.faces_vert.len(), 48);
+        assert_eq!(triple.face_colors.len(), 48);
+
+        // Halves take the color of the atom at their end.
+        for mesh in &[&single, &double, &triple] {
+            for (face, color) in mesh.faces_vert.iter().zip(&mesh.face_colors) {
+                let x = face.iter().map(|id| mesh.vertices[id].position[0]).sum::<f32>() / 4.;
+                assert_eq!(*color, if x < 1. { white } else { red });
+            }
+        }
+
+        // Double bond sticks (radius 0.06) are offset by ±0.09 towards `side`.
+        for v in double.vertices.values() {
+            assert!(v.position[1].abs() > 0.029);
+            assert!(v.position[2].abs() < 0.0601);
+        }
+
+        let aromatic = bond_sticks(start, end, BondOrder::Aromatic, 0.1, 8, (white, red), [0., 1., 0.]);
+        assert_eq!(aromatic.faces_vert.len(), 16 + 3 * 8);
+    }
+
+    #[test]
+    fn uv_sphere_counts_and_normals() {
+        let mesh = uv_sphere(1., 8, 12, [1., 1., 1., 1.]);
+        assert_eq!(mesh.vertices.len(), 2 + 7 * 12);
+        assert_eq!(mesh.faces_vert.len(), 8 * 12);
+
+        for (face, normal) in mesh.faces_vert.iter().zip(&mesh.normals) {
+            let a = mesh.vertices[&face[0]];
+            let winding = mesh.vertices[&face[1]].subtract(&a)
+                .cross(&mesh.vertices[&face[2]].subtract(&a));
+            assert!(dot3(&normal.normal, &a.position) > 0.);
+            assert!(dot3(&normal.normal, &winding.normal) > 0.);
+        }
+    }
+
+    #[test]
+    fn smooth_normals_and_sharing() {
+        use types::Shape;
+
+        // Cylinder sides are smooth: each vertex's normal points straight out
+        // from the axis, whichever face it's on.
+        let (start, end) = ([0., 0., 0.], [0., 0., 2.]);
+        let mesh = cylinder(start, end, 0.5, 12, [1., 1., 1., 1.]);
+        for (face, normals) in mesh.faces_vert.iter().zip(&mesh.vertex_normals) {
+            for (id, n) in face.iter().zip(normals) {
+                let p = mesh.vertices[id].position;
+                assert!((dot3(&n.normal, &[p[0] / 0.5, p[1] / 0.5, 0.]) - 1.).abs() < 1e-4);
+            }
+        }
+
+        // Box edges are sharper than most creases, and stay flat.
+        let mut mesh = box_((1., 1., 1.));
+        smooth_normals(&mut mesh, τ / 8.);
+        for (normals, face_normal) in mesh.vertex_normals.iter().zip(&mesh.normals) {
+            assert!(normals.iter().all(|n| n.normal == face_normal.normal));
+        }
+        smooth_normals(&mut mesh, τ / 2.);
+        let n = mesh.vertex_normals[0][0].normal;
+        assert!((n[0] + 1. / 3_f32.sqrt()).abs() < 1e-5);
+
+        // Smooth spheres share one buffer vertex per mesh vertex; flat boxes need
+        // one per face vertex, since each face's normal differs.
+        let sphere = Shape::new(icosphere(1., 2, [1., 1., 1., 1.]), [0., 0., 0.], [0., 0., 0.]);
+        let (indices, vertices) = sphere.vertex_data();
+        assert_eq!(vertices.len(), 162);
+        assert_eq!(indices.len(), 320 * 3);
+        for tri in indices.chunks(3) {
+            let p = vertices[tri[0] as usize].position;
+            assert!(dot3(&vertices[tri[0] as usize].normal, &p) > 0.99);
+        }
+
+        let cube = Shape::new(box_((1., 1., 1.)), [0., 0., 0.], [0., 0., 0.]);
+        let (indices, vertices) = cube.vertex_data();
+        assert_eq!((indices.len(), vertices.len()), (36, 24));
+    }
+
+    #[test]
+    fn combine_transformed() {
+        use types::Shape;
+
+        // A triangle with sparse ids, rotated, scaled and moved; and a box.
+        let mut vertices = HashMap::new();
+        vertices.insert(10, Vertex::new(1., 0., 0.));
+        vertices.insert(20, Vertex::new(0., 1., 0.));
+        vertices.insert(30, Vertex::new(0., 0., 1.));
+        let red = [1., 0., 0., 1.];
+        let tri = Mesh::new(vertices, vec![vec![10, 20, 30]], vec![red], vec![Normal::new(1., 1., 1.)]);
+        let cube = box_((1., 1., 1.));
+
+        let model = transforms::model(&[0., 0., 5.], &[0.3, 0.5, 0.7], 2.);
+        let combined = combine_meshes(&[(&tri, model), (&cube, transforms::translate(&[3., 0., 0.]))]);
+        assert_eq!(combined.vertices.len(), 3 + 8);
+        assert_eq!(combined.face_colors.len(), 1 + 6);
+        assert_eq!(combined.face_colors[0], red);
+        assert_eq!(combined.tris.len(), 3 + 36);
+
+        // The triangle's normal still faces out of it, and its edges keep their
+        // scaled lengths.
+        let p = |i: usize| combined.vertices[&combined.faces_vert[0][i]].position;
+        let (e1, e2) = (sub_arr(&p(1), &p(0)), sub_arr(&p(2), &p(0)));
+        let n = combined.normals[0].normal;
+        assert!(dot3(&n, &e1).abs() < 1e-4 && dot3(&n, &e2).abs() < 1e-4);
+        assert!(dot3(&n, &cross(&e1, &e2)) > 0.);
+        assert!((dot3(&e1, &e1).sqrt() - 2. * 2_f32.sqrt()).abs() < 1e-4);
+
+        // The box is moved, not rotated.
+        let x_min = combined.faces_vert[1..].iter().flat_map(|f| f.iter())
+            .map(|id| combined.vertices[id].position[0]).fold(f32::MAX, f32::min);
+        assert!((x_min - 2.5).abs() < 1e-5);
+
+        let (indices, buffer) = Shape::new(combined, [0., 0., 0.], [0., 0., 0.]).vertex_data();
+        assert!(indices.iter().all(|i| (*i as usize) < buffer.len()));
+    }
+}
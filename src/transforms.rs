@@ -1,5 +1,5 @@
-use ops::{dot, transpose, mul_arr};
-use types::{Camera, Vec4};
+use ops::{dot, dot_v, inverse, mul_arr};
+use types::{Camera, Quaternion, Vec4};
 
 
 pub fn _dot_mv4(M: [[f32; 4]; 4], v: Vec4) -> Vec4 {
@@ -22,8 +22,8 @@ pub fn _I4() -> [[f32; 4]; 4] {
 }
 
 pub fn rotate(θ: &[f32; 3]) -> [[f32; 4]; 4] {
-    // Homogenous rotation matrix.
-    // todo quaternions??
+    // Homogenous rotation matrix, from Euler angles; used for shapes. Cameras
+    // use quaternions; see `Quaternion::from_euler`.
     let cos_x = θ[0].cos();
     let sin_x = θ[0].sin();
     let cos_y = θ[1].cos();
@@ -78,43 +78,24 @@ fn scale(val: f32) -> [[f32; 4]; 4] {
 }
 
 pub fn proj(cam: &Camera) -> [[f32; 4]; 4] {
-    // Return a homogenous matrix of the type used by vulkan.
+    // Return a homogenous projection matrix of the type used by vulkan, from eye
+    // space, where the camera looks along -z with y up.
 
     // x and y map from -1 to +1, left to right, and top to bottom respectively.
-    // z maps from 0 to 1, back to front.
-
-    // This seems to be the (only?) Reasonable OpenGL-style projection matrix I've
-    // found online... But it's missing the changes Vulkan introduces.
+    // z maps from 0 to 1, near to far. Unlike OpenGL, y points down, and z's
+    // range starts at 0:
     // https://matthewwellings.com/blog/the-new-vulkan-coordinate-system/
 
-    // Ref drawing and calculations in CG notebook.
-
     let a = 1. / (cam.fov / 2.).tan();
     let n = cam.near;  // n and f are code-shorteners.
     let f = cam.far;
 
-//    [
-//        [a / cam.aspect, 0., 0., 0.],
-//        [0., -a, 0., 0.],
-//        [0., 0., -f / (f-n), -f*n/(f-n)],  // todo mess with signs.
-//        [0., 0., -1., 0.],
-//    ]
-    // todo can't get the above to work.
-    dot(
-        [
-            [a / cam.aspect, 0., 0., 0.],
-            [0., a, 0., 0.],
-            [0., 0., (f+n)/(f-n), 2.*f*n/(f-n)],  // negative of std opengl here ?
-            [0., 0., -1., 0.],
-        ],
-
-        [
-            [1., 0., 0., 0.],
-            [0., -1., 0., 0.],
-            [0., 0., 0.5, 0.5],
-            [0., 0., 0., 1.]
-        ]
-    )
+    [
+        [a / cam.aspect, 0., 0., 0.],
+        [0., -a, 0., 0.],
+        [0., 0., f / (n - f), f * n / (n - f)],
+        [0., 0., -1., 0.],
+    ]
 }
 
 pub fn proj_gl(cam: &Camera) -> [[f32; 4]; 4] {
@@ -140,15 +121,49 @@ pub fn model(position: &[f32; 3], orientation: &[f32; 3], scale_val: f32) -> [[f
     dot(T, dot(R, S))
 }
 
-pub fn view(position: &[f32; 3], θ: &[f32; 3]) -> [[f32; 4]; 4] {
-    // Homogenous view matrix.  Position first
-    let inv_posit = mul_arr(position, -1.);
-    let inv_θ = mul_arr(θ, -1.);
+pub fn billboard(position: &[f32; 3], cam_orientation: &Quaternion,
+                 scale_val: f32) -> [[f32; 4]; 4] {
+    // Like `model`, but rotated with the camera, so the shape's x and y axes are
+    // the camera's right and up, and its -z faces the camera.
+    let R = cam_orientation.to_matrix();
+    let S = scale(scale_val);
+    let T = translate(position);
+
+    dot(T, dot(R, S))
+}
 
-    let T = translate(&inv_posit);
-    let R = rotate(&inv_θ);
+pub fn view(position: &[f32; 3], orientation: &Quaternion) -> [[f32; 4]; 4] {
+    // Homogenous view matrix, from world space to eye space. Position first.
+    // Cameras look along +z in world space, but eye space looks along -z, as
+    // `proj` expects; flipping z converts between them.
+    let T = translate(&mul_arr(position, -1.));
+    let R = orientation.conjugate().to_matrix();
 
-    transpose(dot(R, T))
+    let flip_z = [
+        [1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., 0., -1., 0.],
+        [0., 0., 0., 1.],
+    ];
+
+    dot(flip_z, dot(R, T))
+}
+
+pub fn unproject(cam: &Camera, ndc: [f32; 3]) -> Option<[f32; 3]> {
+    // The world-space point that projects to `ndc`, in Vulkan's normalized
+    // device coordinates: x and y from -1 to 1, left to right and top to bottom,
+    // and z from 0 to 1, near to far. None if the camera can't be inverted, eg
+    // with a zero fov or aspect ratio.
+    let PV = dot(proj(cam), view(&cam.position, &cam.orientation));
+    let inv = inverse(PV)?;
+
+    let p = dot_v(&inv, [ndc[0], ndc[1], ndc[2], 1.]);
+    let result = [p[0] / p[3], p[1] / p[3], p[2] / p[3]];
+    if result.iter().all(|v| v.is_finite()) {
+        Some(result)
+    } else {
+        None
+    }
 }
 
 
@@ -157,7 +172,7 @@ mod tests {
     use super::*;
 
     use std::f32::consts::PI;
-    use ops::{dot_v, div_arr4};
+    use ops::div_arr4;
     use types::Camera;
 
     // These transforms assume a vulkan coordinate system: Y points down,
@@ -180,7 +195,7 @@ mod tests {
         // is correct; but can indicate a bogus one.
         let cam = Camera {
             position: [0., 0., 0.],
-            θ: [0., 0., 0.],
+            orientation: Quaternion::identity(),
             fov: τ / 4.,
             aspect: 1.,
             near: 1.,
@@ -228,28 +243,114 @@ mod tests {
         assert!(arr_close(div_arr4(&ar_btm, ar_btm[3]), [1., 1., 0., 1.]));
     }
 
-//    #[test]
-//    fn depth_buffer() {
-//        // Make sure the nonlinear depth of the projection matrix works.
-//           let cam = Camera {
-//            position: [0., 0., 0.],
-//            θ: [0., 0., 0.],
-//            fov: τ / 4.,
-//            aspect: 1.,
-//            near: 1.,
-//            far: 100.
-//        };
-//
-//        let P = proj(&cam);
-//        let wf = 200.;  // Width of the FOV at the far end. Also height.
-//        let wn = 2.;  // ... at the near end.
-//
-//        // These four corners are for the top of the frustum.
-//        let test_pt = [-90., 0., 100., 1.];
-//        let projected = dot_v(&P, test_pt);
-//
-//        // We divide by W, which is a the (unmodified) z component.
-////        assert!(arr_close(div_arr4(&fl, fl[3]), [-1., -1., 1., 1.]));
-//        assert_eq!(div_arr4(&projected, projected[3]), [-1., 0., 1., 1.]);
-//    }
+    #[test]
+    fn depth_buffer() {
+        // Make sure the nonlinear depth of the projection matrix works: depth rises
+        // quickly just past the near plane, and is monotonic.
+        let cam = Camera {
+            position: [0., 0., 0.],
+            orientation: Quaternion::identity(),
+            fov: τ / 4.,
+            aspect: 1.,
+            near: 1.,
+            far: 100.
+        };
+
+        let P = proj(&cam);
+        let depth = |dist: f32| {
+            let projected = dot_v(&P, [0., 0., -dist, 1.]);
+            projected[2] / projected[3]
+        };
+
+        // z_ndc = f(d - n) / (d(f - n)), for a point at distance d.
+        assert!((depth(2.) - 100. / 198.).abs() < 1e-6);
+        assert!((depth(50.) - 49. * 100. / (50. * 99.)).abs() < 1e-6);
+
+        let mut prev = depth(1.);
+        for i in 2..100 {
+            let d = depth(i as f32);
+            assert!(d > prev);
+            prev = d;
+        }
+
+        // Off-axis points scale with distance; this one's 9/10 of the way to the left edge.
+        let test_pt = [-90., 0., -100., 1.];
+        let projected = dot_v(&P, test_pt);
+        let ndc = div_arr4(&projected, projected[3]);
+        assert!((ndc[0] + 0.9).abs() < 1e-6);
+        assert!(ndc[1].abs() < 1e-6);
+        assert!((ndc[2] - 1.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn view_looks_along_z() {
+        // A camera behind the origin sees it in the center of the screen; one
+        // yawed a quarter turn right sees +x there instead.
+        let mut cam = Camera {
+            position: [0., 0., -7.],
+            orientation: Quaternion::identity(),
+            fov: τ / 4.,
+            aspect: 1.,
+            near: 0.1,
+            far: 100.
+        };
+        let ndc = |cam: &Camera, pt: [f32; 3]| {
+            let clip = dot_v(&dot(proj(cam), view(&cam.position, &cam.orientation)),
+                             [pt[0], pt[1], pt[2], 1.]);
+            div_arr4(&clip, clip[3])
+        };
+
+        let center = ndc(&cam, [0., 0., 0.]);
+        assert!(center[0].abs() < 1e-6 && center[1].abs() < 1e-6);
+        assert!(center[2] > 0. && center[2] < 1.);
+
+        // +x is right, and +y is up; ie towards the top of the screen at -1.
+        assert!(ndc(&cam, [1., 0., 0.])[0] > 0.);
+        assert!(ndc(&cam, [0., 1., 0.])[1] < 0.);
+
+        cam.position = [0., 0., 0.];
+        cam.orientation = Quaternion::from_axis_angle(&[0., 1., 0.], τ / 4.);
+        let right = ndc(&cam, [5., 0., 0.]);
+        assert!(right[0].abs() < 1e-5 && right[1].abs() < 1e-5);
+
+        // Unprojecting undoes it.
+        let pt = unproject(&cam, [right[0], right[1], right[2]]).unwrap();
+        assert!((pt[0] - 5.).abs() < 1e-3 && pt[1].abs() < 1e-3 && pt[2].abs() < 1e-3);
+
+        // A zero fov can't be undone.
+        cam.fov = 0.;
+        assert_eq!(unproject(&cam, [0., 0., 0.5]), None);
+    }
+
+    #[test]
+    fn quaternions() {
+        let q = Quaternion::from_axis_angle(&[0., 0., 1.], τ / 4.);
+        let v = q.rotate_vec(&[1., 0., 0.]);
+        assert!(arr_close([v[0], v[1], v[2], 0.], [0., 1., 0., 0.]));
+
+        // Rotations compose, and match matrices.
+        let θ = [0.3, -1.2, 2.];
+        let q = Quaternion::from_euler(&θ);
+        let (M, R) = (q.to_matrix(), rotate(&θ));
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((M[i][j] - R[i][j]).abs() < 1e-6);
+            }
+        }
+        let v = q.rotate_vec(&[1., 2., 3.]);
+        let w = dot_v(&R, [1., 2., 3., 1.]);
+        assert!((v[0] - w[0]).abs() < 1e-5 && (v[1] - w[1]).abs() < 1e-5 && (v[2] - w[2]).abs() < 1e-5);
+
+        // Pitching straight up doesn't lock yaw and roll together, as Euler angles
+        // would: yawing afterwards still turns about the camera's own up axis.
+        let pitched = Quaternion::from_axis_angle(&[1., 0., 0.], -τ / 4.);
+        let yawed = pitched * Quaternion::from_axis_angle(&[0., 1., 0.], τ / 4.);
+        let rolled = pitched * Quaternion::from_axis_angle(&[0., 0., 1.], τ / 4.);
+        let (fy, fr) = (yawed.rotate_vec(&[0., 0., 1.]), rolled.rotate_vec(&[0., 0., 1.]));
+        assert!((fy[0] - 1.).abs() < 1e-6);
+        assert!((fr[1] - 1.).abs() < 1e-6);
+
+        let back = q * q.conjugate();
+        assert!(arr_close([back.w, back.x, back.y, back.z], [1., 0., 0., 0.]));
+    }
 }
@@ -1,6 +1,12 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::io;
 use std::ops::{Add, Sub, Mul};
 
+use molecule::Molecule;
+use ops::{add_arr, cross, dot3, mul_arr, normalize, sub_arr, transpose};
+use transforms;
+
 // todo ndarrays, or builtin arrays? We need to enforce length of items.
 
 //#[derive(Debug)]
@@ -60,6 +66,83 @@ impl Sub for Vec4 {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    // A rotation, as a unit quaternion. w is the scalar part.
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Quaternion {
+    pub fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
+        Self { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1., 0., 0., 0.)
+    }
+
+    pub fn from_axis_angle(axis: &[f32; 3], angle: f32) -> Self {
+        // A rotation of `angle` radians about `axis`, counter-clockwise looking
+        // down the axis towards the origin.
+        let axis = normalize(axis);
+        let s = (angle / 2.).sin();
+        Self::new((angle / 2.).cos(), axis[0] * s, axis[1] * s, axis[2] * s)
+    }
+
+    pub fn from_euler(θ: &[f32; 3]) -> Self {
+        // The same rotation as `transforms::rotate`: about x, then y, then z.
+        Self::from_axis_angle(&[0., 0., 1.], θ[2]) *
+            Self::from_axis_angle(&[0., 1., 0.], θ[1]) *
+            Self::from_axis_angle(&[1., 0., 0.], θ[0])
+    }
+
+    pub fn conjugate(&self) -> Self {
+        // For unit quaternions, this is the inverse rotation.
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn normalize(&self) -> Self {
+        // Repeatedly composing rotations accumulates floating point error;
+        // normalize to keep the quaternion a rotation.
+        let mag = (self.w.powi(2) + self.x.powi(2) + self.y.powi(2) + self.z.powi(2)).sqrt();
+        Self::new(self.w / mag, self.x / mag, self.y / mag, self.z / mag)
+    }
+
+    pub fn rotate_vec(&self, v: &[f32; 3]) -> [f32; 3] {
+        // Equivalent to q * v * q^-1, without building the intermediate quaternions.
+        let u = [self.x, self.y, self.z];
+        let t = mul_arr(&cross(&u, v), 2.);
+        add_arr(&add_arr(v, &mul_arr(&t, self.w)), &cross(&u, &t))
+    }
+
+    pub fn to_matrix(&self) -> [[f32; 4]; 4] {
+        // Homogenous rotation matrix.
+        let (w, x, y, z) = (self.w, self.x, self.y, self.z);
+        [
+            [1. - 2. * (y * y + z * z), 2. * (x * y - w * z), 2. * (x * z + w * y), 0.],
+            [2. * (x * y + w * z), 1. - 2. * (x * x + z * z), 2. * (y * z - w * x), 0.],
+            [2. * (x * z - w * y), 2. * (y * z + w * x), 1. - 2. * (x * x + y * y), 0.],
+            [0., 0., 0., 1.],
+        ]
+    }
+}
+
+impl Mul for Quaternion {
+    type Output = Self;
+
+    fn mul(self, other: Quaternion) -> Quaternion {
+        // The Hamilton product; applies `other`'s rotation first, then `self`'s.
+        Quaternion {
+            w: self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            x: self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            y: self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            z: self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+        }
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
@@ -136,52 +219,60 @@ pub struct Mesh {
     pub faces_vert: Vec<Vec<u32>>,  // Indicies of vertexes.
     pub face_colors: Vec<[f32; 4]>,  // These index corresopnd to faces_vert indices.
     pub normals: Vec<Normal>,  // Normals only use the 3d component; not defined for 4d, yet. ?
+    // Optional smooth normals, for each vertex of each face; these index
+    // correspond to faces_vert indices. Empty for flat shading, using `normals`.
+    // See `shape_maker::smooth_normals`.
+    pub vertex_normals: Vec<Vec<Normal>>,
     pub tris: Vec<u32>,
 }
 
 impl Mesh {
     pub fn new(vertices: HashMap<u32, Vertex>,
                faces_vert: Vec<Vec<u32>>, face_colors: Vec<[f32; 4]>, normals: Vec<Normal>) -> Mesh {
+        // For meshes whose faces we know are valid, eg from `shape_maker`. Use
+        // `try_new` for meshes from elsewhere.
+        match Self::try_new(vertices, faces_vert, face_colors, normals) {
+            Ok(result) => result,
+            Err(e) => panic!("Invalid mesh: {}", e),
+        }
+    }
 
-        let mut result = Mesh {vertices, faces_vert, face_colors, normals, tris: Vec::new()};
-        result.make_tris();
-        result
+    pub fn try_new(vertices: HashMap<u32, Vertex>, faces_vert: Vec<Vec<u32>>,
+                   face_colors: Vec<[f32; 4]>, normals: Vec<Normal>) -> Result<Mesh, MeshError> {
+        let mut result = Mesh {vertices, faces_vert, face_colors, normals, vertex_normals: Vec::new(),
+                               tris: Vec::new()};
+        result.make_tris()?;
+        Ok(result)
     }
 
-    pub fn make_tris(&mut self) {
+    pub fn make_tris(&mut self) -> Result<(), MeshError> {
         // Divide faces into triangles of indices. These indices aren't of node
         // ids; rather of cumulative node ids; eg how they'll appear in an index buffer.
         // Result is a 1d array.
-        // Important: Faces must be defined in an order of consecutive edges.
+        // Important: Faces must be defined in an order of consecutive edges, and
+        // be planar, or close to it. They may be concave.
         // If we modify/add faces, we must re-run this.
         let mut result = Vec::new();
         let mut current_i = 0;
 
-        for face in &self.faces_vert {
-            match face.len() {
-                3 => {
-                    // Only one triangle.
-                    result.push(current_i as u32);
-                    result.push(current_i as u32 + 1);
-                    result.push(current_i as u32 + 2);
-                },
-                4 => {
-                    // First triangle
-                    result.push(current_i as u32);
-                    result.push(current_i as u32 + 1);
-                    result.push(current_i as u32 + 2);
-                    // Second triangle
-                    result.push(current_i as u32);
-                    result.push(current_i as u32 + 2);
-                    result.push(current_i as u32 + 3);
-                },
-                2 => panic!("Faces must have length 3 or more."),
-                _ => panic!("Error: Haven't implemented faces with vertex counds higher than four.")
-
+        for (i, face) in self.faces_vert.iter().enumerate() {
+            if face.len() < 3 {
+                return Err(MeshError::TooFewVertices { face: i });
             }
-            current_i += face.len();
+            let mut points = Vec::new();
+            for id in face {
+                match self.vertices.get(id) {
+                    Some(vertex) => points.push(vertex.position),
+                    None => return Err(MeshError::UnknownVertex { face: i, id: *id }),
+                }
+            }
+
+            let tris = triangulate(&points).ok_or(MeshError::Degenerate { face: i })?;
+            result.extend(tris.iter().map(|t| current_i + t));
+            current_i += face.len() as u32;
         }
-        self.tris = result
+        self.tris = result;
+        Ok(())
     }
 
     pub fn num_face_verts(&self) -> u32 {
@@ -191,6 +282,74 @@ impl Mesh {
     }
 }
 
+fn triangulate(points: &[[f32; 3]]) -> Option<Vec<u32>> {
+    // Triangles for a polygon, as indices into its points, with the polygon's
+    // winding. Convex polygons are fanned from their first point; concave ones
+    // are split by ear clipping. None if the polygon has no area, eg its points
+    // are collinear, or ear clipping gets stuck. We don't check for edges that
+    // cross; most self-intersecting polygons give overlapping triangles.
+    if points.len() == 3 {
+        return Some(vec![0, 1, 2]);
+    }
+
+    // Newell's method; this handles concave polygons, unlike a cross product
+    // at any one corner. Its length is twice the polygon's area.
+    let mut normal = [0.; 3];
+    for (i, p) in points.iter().enumerate() {
+        let q = points[(i + 1) % points.len()];
+        normal[0] += (p[1] - q[1]) * (p[2] + q[2]);
+        normal[1] += (p[2] - q[2]) * (p[0] + q[0]);
+        normal[2] += (p[0] - q[0]) * (p[1] + q[1]);
+    }
+    let area2 = dot3(&normal, &normal).sqrt();
+    if area2 < 1e-12 {
+        return None;
+    }
+    let normal = normalize(&normal);
+    let ϵ = 1e-6 * area2;
+
+    // Positive if a, b, c turn the same way as the polygon.
+    let turn = |a: usize, b: usize, c: usize| {
+        let (ab, bc) = (sub_arr(&points[b], &points[a]), sub_arr(&points[c], &points[b]));
+        dot3(&cross(&ab, &bc), &normal)
+    };
+
+    let n = points.len();
+    if (0..n).all(|i| turn((i + n - 1) % n, i, (i + 1) % n) >= -ϵ) {
+        return Some((1..n as u32 - 1).flat_map(|i| vec![0, i, i + 1]).collect());
+    }
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut result = Vec::new();
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let corner = |j: usize| (remaining[(j + m - 1) % m], remaining[j], remaining[(j + 1) % m]);
+
+        // An ear is a convex corner whose triangle contains no other point.
+        let ear = (0..m).find(|&j| {
+            let (a, b, c) = corner(j);
+            turn(a, b, c) > ϵ && !remaining.iter().any(|&k| {
+                k != a && k != b && k != c &&
+                    turn(a, b, k) >= 0. && turn(b, c, k) >= 0. && turn(c, a, k) >= 0.
+            })
+        });
+        match ear {
+            Some(j) => {
+                let (a, b, c) = corner(j);
+                result.extend_from_slice(&[a as u32, b as u32, c as u32]);
+                remaining.remove(j);
+            }
+            // Points in a straight line don't need a triangle; drop them.
+            None => match (0..m).find(|&j| { let (a, b, c) = corner(j); turn(a, b, c).abs() <= ϵ }) {
+                Some(j) => { remaining.remove(j); }
+                None => return None,
+            },
+        }
+    }
+    result.extend(remaining.iter().map(|&i| i as u32));
+    Some(result)
+}
+
 #[derive(Clone, Debug)]
 pub struct Shape {
     // todo macro constructor that lets you ommit position, rotation, scale.
@@ -202,6 +361,8 @@ pub struct Shape {
     pub rotation_speed: [f32; 3],
     pub opacity: f32,
     pub specular_intensity: f32,
+    // Billboards ignore orientation, and turn to face the camera; eg labels.
+    pub billboard: bool,
 }
 
 impl Shape {
@@ -213,8 +374,49 @@ impl Shape {
             orientation,
             rotation_speed: [0., 0., 0.],
             opacity: 1.,
-            specular_intensity: 1.
+            specular_intensity: 1.,
+            billboard: false,
+        }
+    }
+
+    pub fn vertex_data(&self) -> (Vec<u32>, Vec<ShaderVertex>) {
+        // Make the contents of this shape's index and vertex buffers. Used by
+        // both the Vulkan and software renderers.
+        // Each shape gets its own buffers, so indices start at 0.
+        // Face vertices with the same position, normal, and color share a
+        // buffer vertex; eg everywhere on smooth meshes, and within flat faces.
+        let mut vertex_info = Vec::new();
+        let mut shared: HashMap<(u32, [u32; 3], [u32; 4]), u32> = HashMap::new();
+        // Buffer indices of each face vertex, in the order `Mesh::tris` counts them.
+        let mut buffer_ids = Vec::new();
+
+        for (i, face) in self.mesh.faces_vert.iter().enumerate() {
+            for (j, vert_id) in face.iter().enumerate() {
+                let normal = if self.mesh.vertex_normals.is_empty() {
+                    self.mesh.normals[i]
+                } else {
+                    self.mesh.vertex_normals[i][j]
+                };
+                let color = self.mesh.face_colors[i];
+
+                let n = normal.normal;
+                let key = (
+                    *vert_id,
+                    [n[0].to_bits(), n[1].to_bits(), n[2].to_bits()],
+                    [color[0].to_bits(), color[1].to_bits(), color[2].to_bits(), color[3].to_bits()],
+                );
+                let id = *shared.entry(key).or_insert_with(|| {
+                    vertex_info.push(ShaderVertex::new(
+                        self.mesh.vertices[vert_id], normal, color, self.specular_intensity,
+                    ));
+                    vertex_info.len() as u32 - 1
+                });
+                buffer_ids.push(id);
+            }
         }
+
+        let indices = self.mesh.tris.iter().map(|i| buffer_ids[*i as usize]).collect();
+        (indices, vertex_info)
     }
 }
 
@@ -223,7 +425,9 @@ pub struct Camera {
     // Position shifts all points prior to the camera transform; this is what
     // we adjust with move keys.
     pub position: [f32; 3],
-    pub θ: [f32; 3],
+    // Rotates the camera's axes into world space. With the identity, the camera
+    // looks along +z, with +y up and +x to the right.
+    pub orientation: Quaternion,
 
     pub fov: f32,  // Vertical field of view in radians.
     pub aspect: f32,  // width / height.
@@ -234,6 +438,18 @@ pub struct Camera {
 }
 
 impl Camera {
+    pub fn forward(&self) -> [f32; 3] {
+        self.orientation.rotate_vec(&[0., 0., 1.])
+    }
+
+    pub fn right(&self) -> [f32; 3] {
+        self.orientation.rotate_vec(&[1., 0., 0.])
+    }
+
+    pub fn up(&self) -> [f32; 3] {
+        self.orientation.rotate_vec(&[0., 1., 0.])
+    }
+
     pub fn _view_size(&self, far: bool) -> (f32, f32){
         // Calculate the projected window width and height, using basic trig.
         let dist = if far { self.far } else { self.near };
@@ -245,13 +461,21 @@ impl Camera {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum CameraType {
     Single,  // No camera changes; rotate the shape instead
     // Move foward, back, left, right, and look around. No roll look.  Not sure
     // which 4d rotations/movement to allow or block.
     FPS,
     Free, // No restriction on movement
+    Orbit,  // Circle `Scene::orbit`'s target, facing it.
+}
+
+#[derive(Clone, Debug)]
+pub struct Orbit {
+    // Where orbit cameras look, and how far they are from it. See `orbit.rs`.
+    pub target: [f32; 3],
+    pub distance: f32,
 }
 
 #[derive(Clone, Debug)]
@@ -273,16 +497,205 @@ pub struct Lighting {
     pub sources: Vec<LightSource>,
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct Uniforms {
+    // Per-shape data passed to the shaders; mirrors `Data` in vert.glsl. Matrices
+    // are as uploaded; GLSL reads them column-major, ie transposed.
+    pub model: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+
+    pub r_model: [[f32; 4]; 4],
+    pub t_model: [[f32; 4]; 4],
+
+    pub ambient_color: [f32; 4],
+    pub diffuse_color: [f32; 4],
+    pub diffuse_direction: [f32; 3],
+
+    pub ambient_intensity: f32,
+    pub diffuse_intensity: f32,
+    pub shape_opacity: f32,
+    pub highlight: f32,  // 0 for normal shading, to 1 for fully highlighted.
+}
+
+impl Uniforms {
+    pub fn new(shape: &Shape, view: [[f32; 4]; 4], proj: [[f32; 4]; 4],
+               lighting: &Lighting) -> Self {
+        // view and proj are passed in, so they can be computed once per frame.
+        // We transpose every matrix, so the shaders see them as we compute them.
+        Self {
+            // todo don't repeat things other than model here!!
+            model: transpose(transforms::model(&shape.position, &shape.orientation, shape.scale)),
+
+            r_model: transpose(transforms::rotate(&shape.orientation)),
+            t_model: transpose(transforms::translate(&shape.position)),
+
+            view: transpose(view),
+            proj: transpose(proj),
+
+            ambient_color: lighting.ambient_color,
+            diffuse_color: lighting.diffuse_color,
+            diffuse_direction: lighting.diffuse_direction,
+
+            ambient_intensity: lighting.ambient_intensity,
+            diffuse_intensity: lighting.diffuse_intensity,
+            shape_opacity: 1.,
+            highlight: 0.,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Representation {
+    BallAndStick,  // Small atom spheres, with sticks showing bond order.
+    SpaceFilling,  // CPK; atom spheres at van der Waals radii. No bonds.
+    Licorice,  // Equal-radius sticks with rounded ends.
+    Wireframe,  // Thin lines for bonds only.
+}
+
+impl Representation {
+    pub fn next(self) -> Self {
+        // For cycling through representations with a single key.
+        match self {
+            Representation::BallAndStick => Representation::SpaceFilling,
+            Representation::SpaceFilling => Representation::Licorice,
+            Representation::Licorice => Representation::Wireframe,
+            Representation::Wireframe => Representation::BallAndStick,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        // Names as used on the command line.
+        match name.to_lowercase().as_ref() {
+            "ball-stick" | "ball-and-stick" => Some(Representation::BallAndStick),
+            "space-filling" | "spacefill" | "cpk" => Some(Representation::SpaceFilling),
+            "licorice" | "sticks" => Some(Representation::Licorice),
+            "wireframe" | "lines" => Some(Representation::Wireframe),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Scene {
     pub shapes: HashMap<u32, Shape>,
     pub cam: Camera,
     pub cam_type: CameraType,
+    pub orbit: Orbit,
     pub lighting: Lighting,
     pub sensitivities: (f32, f32, f32),  // move, rotate, zoom
+    // Scenes built from a molecule keep it, so shapes can be rebuilt, eg when
+    // changing representation.
+    pub molecule: Option<Molecule>,
+    pub representation: Representation,
+    pub selection: Vec<usize>,  // Atom indices, in the order they were selected.
 }
 
 //#[derive(Debug)]
 //pub struct _Animation {
 //    pub frames: HashMap<f32, Scene>  // <time, scene>
-//}
\ No newline at end of file
+//}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BondOrder {
+    Single,
+    Double,
+    Triple,
+    Aromatic,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshError {
+    // Returned by `Mesh::make_tris`. `face` is an index into `faces_vert`.
+    TooFewVertices { face: usize },
+    UnknownVertex { face: usize, id: u32 },
+    Degenerate { face: usize },  // No area, eg collinear; or no ear to clip.
+}
+
+impl fmt::Display for MeshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshError::TooFewVertices { face } => write!(f, "Face {} has fewer than 3 vertices", face),
+            MeshError::UnknownVertex { face, id } => write!(f, "Face {} has unknown vertex {}", face, id),
+            MeshError::Degenerate { face } => write!(f, "Face {} can't be triangulated", face),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    // Returned by the molecule file readers.
+    Io(io::Error),
+    Syntax { line: usize, msg: String },  // line is 1-based.
+    UnknownFormat(String),  // The file's extension.
+}
+
+impl ParseError {
+    pub fn syntax(line: usize, msg: &str) -> Self {
+        ParseError::Syntax { line, msg: msg.to_string() }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "IO error: {}", e),
+            ParseError::Syntax { line, msg } => write!(f, "Line {}: {}", line, msg),
+            ParseError::UnknownFormat(ext) => write!(f, "Unknown file format: \"{}\"", ext),
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::PI;
+
+    fn polygon(points: &[[f32; 2]]) -> Result<Mesh, MeshError> {
+        // A single face in the xy plane.
+        let vertices = points.iter().enumerate()
+            .map(|(i, p)| (i as u32, Vertex::new(p[0], p[1], 0.))).collect();
+        let face = (0..points.len() as u32).collect();
+        Mesh::try_new(vertices, vec![face], vec![[1., 1., 1., 1.]], vec![Normal::new(0., 0., 1.)])
+    }
+
+    fn area(mesh: &Mesh) -> f32 {
+        mesh.tris.chunks(3).map(|t| {
+            let p = |i: u32| mesh.vertices[&mesh.faces_vert[0][i as usize]];
+            p(t[1]).subtract(&p(t[0])).cross(&p(t[2]).subtract(&p(t[0]))).normal[2] / 2.
+        }).sum()
+    }
+
+    #[test]
+    fn triangulation() {
+        // Convex polygons are fanned; quads triangulate as before.
+        let square = polygon(&[[0., 0.], [1., 0.], [1., 1.], [0., 1.]]).unwrap();
+        assert_eq!(square.tris, vec![0, 1, 2, 0, 2, 3]);
+
+        let hexagon: Vec<[f32; 2]> = (0..6).map(|i| {
+            let θ = i as f32 * PI / 3.;
+            [θ.cos(), θ.sin()]
+        }).collect();
+        let hexagon = polygon(&hexagon).unwrap();
+        assert_eq!(hexagon.tris.len(), 12);
+        assert!((area(&hexagon) - 3. * 3f32.sqrt() / 2.).abs() < 1e-5);
+
+        // Concave: an L, whose fan from the first point would cross outside it.
+        // Every triangle keeps the polygon's winding, and they cover its area.
+        let l = polygon(&[[0., 0.], [2., 0.], [2., 1.], [1., 1.], [1., 2.], [0., 2.]]).unwrap();
+        assert_eq!(l.tris.len(), 12);
+        assert!((area(&l) - 3.).abs() < 1e-5);
+        let arrow = polygon(&[[0., 0.], [4., 2.], [0., 4.], [1., 2.], [0., 0.5]]).unwrap();
+        assert!((area(&arrow) - 6.25).abs() < 1e-5);
+
+        assert_eq!(polygon(&[[0., 0.], [1., 0.]]).unwrap_err(), MeshError::TooFewVertices { face: 0 });
+        assert_eq!(polygon(&[[0., 0.], [1., 0.], [2., 0.], [3., 0.]]).unwrap_err(),
+                   MeshError::Degenerate { face: 0 });
+    }
+}
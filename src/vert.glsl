@@ -9,6 +9,7 @@ layout(location = 0) out vec3 v_normal;
 layout(location = 1) out vec4 face_color2;
 layout(location = 2) out vec3 diffuse_direction;
 layout(location = 3) out vec4 ambient_color;
+layout(location = 4) out float highlight;
 
 layout(set = 0, binding = 0) uniform Data {
     mat4 model;
@@ -26,6 +27,7 @@ layout(set = 0, binding = 0) uniform Data {
     float diffuse_intensity;
 
     float shape_opacity;
+    float highlight;
 } uniforms;
 
 void main() {
@@ -45,4 +47,5 @@ void main() {
     face_color2 = face_color;
     diffuse_direction = uniforms.diffuse_direction;
     ambient_color = uniforms.ambient_color;
+    highlight = uniforms.highlight;
 }
\ No newline at end of file
@@ -0,0 +1,112 @@
+// Reads XYZ molecular geometry files. Each frame is an atom count line, a
+// comment line, then one `Element x y z` line per atom; files may contain
+// several frames back to back, eg from a trajectory or optimization.
+
+use std::fs;
+use std::path::Path;
+
+use types::ParseError;
+
+#[derive(Clone, Debug)]
+pub struct XyzAtom {
+    pub element: String,  // Symbol, capitalized conventionally. eg "Cl".
+    pub position: [f32; 3],  // Å
+}
+
+#[derive(Clone, Debug)]
+pub struct XyzFrame {
+    pub comment: String,
+    pub atoms: Vec<XyzAtom>,
+}
+
+pub fn normalize_symbol(symbol: &str) -> String {
+    // "CL", "cl" and "Cl" all refer to chlorine.
+    let mut chars = symbol.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(|c| c.to_lowercase())).collect(),
+        None => String::new(),
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<XyzFrame>, ParseError> {
+    let mut frames = Vec::new();
+    // Line numbers are 1-based, for error messages.
+    let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l)).peekable();
+
+    loop {
+        // Allow blank lines between frames, and at the end of the file.
+        while let Some(&(_, line)) = lines.peek() {
+            if line.trim().is_empty() { lines.next(); } else { break }
+        }
+
+        let (count_line, count_text) = match lines.next() {
+            Some(l) => l,
+            None => break,
+        };
+        let count: usize = count_text.trim().parse()
+            .map_err(|_| ParseError::syntax(count_line, "Expected an atom count"))?;
+
+        let comment = match lines.next() {
+            Some((_, l)) => l.trim().to_string(),
+            None => return Err(ParseError::syntax(count_line + 1, "Missing comment line")),
+        };
+
+        let mut atoms = Vec::with_capacity(count);
+        for i in 0..count {
+            let (line_num, line) = lines.next().ok_or_else(|| ParseError::syntax(
+                count_line + 2 + i,
+                &format!("Expected {} atoms; file ended after {}", count, i)
+            ))?;
+
+            // Columns past the coordinates (eg forces or charges) are ignored.
+            let cols: Vec<&str> = line.split_whitespace().collect();
+            if cols.len() < 4 {
+                return Err(ParseError::syntax(line_num, "Expected `Element x y z`"));
+            }
+
+            let mut position = [0.; 3];
+            for j in 0..3 {
+                position[j] = cols[j + 1].parse()
+                    .map_err(|_| ParseError::syntax(line_num, "Invalid coordinate"))?;
+            }
+
+            atoms.push(XyzAtom { element: normalize_symbol(cols[0]), position });
+        }
+
+        frames.push(XyzFrame { comment, atoms });
+    }
+
+    Ok(frames)
+}
+
+pub fn load(path: &Path) -> Result<Vec<XyzFrame>, ParseError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multi_frame() {
+        let text = "3\nwater\nO 0.0 0.0 0.117\nh 0.0 0.757 -0.467\nH 0.0 -0.757 -0.467\n\
+                    1\n\nCL 1 2 3 0.5\n\n";
+
+        let frames = parse(text).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].comment, "water");
+        assert_eq!(frames[0].atoms.len(), 3);
+        assert_eq!(frames[0].atoms[1].element, "H");
+        assert_eq!(frames[0].atoms[1].position, [0., 0.757, -0.467]);
+        assert_eq!(frames[1].atoms[0].element, "Cl");
+        assert_eq!(frames[1].atoms[0].position, [1., 2., 3.]);
+    }
+
+    #[test]
+    fn truncated() {
+        match parse("3\ncomment\nC 0 0 0\n") {
+            Err(ParseError::Syntax { line, .. }) => assert_eq!(line, 4),
+            r => panic!("Expected a syntax error; got {:?}", r),
+        }
+    }
+}